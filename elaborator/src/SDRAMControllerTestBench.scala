@@ -51,9 +51,15 @@ object SDRAMControllerTestBenchMain extends Elaborator {
   case class SDRAMControllerTestBenchParameterMain(
     @arg(name = "sdramControllerParameter") sdramControllerParameter: SDRAMControllerParameterMain,
     @arg(name = "testVerbatimParameter") testVerbatimParameter:       TestVerbatimParameterMain,
-    @arg(name = "timeout") timeout:                                   Int) {
+    @arg(name = "timeout") timeout:                                   Int,
+    @arg(name = "useDeviceModel") useDeviceModel:                     Boolean = false) {
     def convert: SDRAMControllerTestBenchParameter =
-      SDRAMControllerTestBenchParameter(sdramControllerParameter.convert, testVerbatimParameter.convert, timeout)
+      SDRAMControllerTestBenchParameter(
+        sdramControllerParameter.convert,
+        testVerbatimParameter.convert,
+        timeout,
+        useDeviceModel
+      )
   }
 
   def main(args: Array[String]): Unit = ParserForMethods(this).runOrExit(args)
@@ -49,8 +49,8 @@ in
   };
 
   # TODO: designConfig should be read from OM
-  tbConfig = with builtins;
-    fromJSON (readFile ./../../configs/${tbTarget}Main.json);
+  tbConfigPath = ./../../configs/${tbTarget}Main.json;
+  tbConfig = with builtins; fromJSON (readFile scope.tbConfigPath);
 
 })
 
@@ -4,6 +4,7 @@
 { lib
 , rustPlatform
 , tbConfig
+, tbConfigPath
 , dpiLibName
 , sv2023 ? true
 , vpi ? false
@@ -14,13 +15,18 @@
 rustPlatform.buildRustPackage rec {
   name = "dpi-lib";
   src = ./../../${dpiLibName};
-  cargoHash = "sha256-Bqq3zA807Dr+QBKfaAjxkNgJ2ZzSco0sUJ2ioNfHACk=";
+  # every dependency comes from crates.io with its checksum in the lock file,
+  # so there is no vendor hash to keep in step with Cargo.toml
+  cargoLock.lockFile = ./../../${dpiLibName}/Cargo.lock;
   buildFeatures = lib.optionals sv2023 [ "sv2023" ]
     ++ lib.optionals vpi [ "vpi" ] ++ lib.optionals enable-trace [ "trace" ];
 
   env = {
     TIMEOUT = tbConfig.timeout;
     CLOCK_FLIP_TIME = tbConfig.testVerbatimParameter.clockFlipTick * timescale;
+    TIMESCALE = timescale;
+    # default for `+rtl-config=`
+    RTL_CONFIG = "${tbConfigPath}";
   };
 
   passthru = {
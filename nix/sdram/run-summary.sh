@@ -0,0 +1,16 @@
+# Sourced by the simulator wrappers. The simulator exits 0 however the run
+# ended, the run summary tells: succeed only if its result is PASS. The
+# summary is read from the first +run-summary=<path> among the arguments, as
+# sdramemu takes it, run-summary.json by default. Needs _JQ_BIN set.
+
+_run_summary_status() {
+  local _summary=run-summary.json
+  local _arg
+  for _arg in "$@"; do
+    if [[ "$_arg" == +run-summary=* ]]; then
+      _summary=${_arg#+run-summary=}
+      break
+    fi
+  done
+  [[ "$("$_JQ_BIN" -r .result "$_summary" 2>/dev/null)" == PASS ]]
+}
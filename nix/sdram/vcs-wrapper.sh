@@ -7,10 +7,13 @@ if ((${VERBOSE:-0})); then
 fi
 
 _DATE_BIN=@dateBin@
+_JQ_BIN=@jqBin@
 _VCS_SIM_BIN=@vcsSimBin@
 _VCS_SIM_DAIDIR=@vcsSimDaidir@
 _VCS_FHS_ENV=@vcsFhsEnv@
 
+source @runSummary@
+
 _NOW=$("$_DATE_BIN" "+%Y-%m-%d-%H-%M-%S")
 _SDRAM_SIM_RESULT_DIR=${SDRAM_SIM_RESULT_DIR:-"sdram-sim-result"}
 _CURRENT="$_SDRAM_SIM_RESULT_DIR"/all/"$_NOW"
@@ -31,6 +34,11 @@ export LD_LIBRARY_PATH="$PWD/$_daidir:$LD_LIBRARY_PATH"
 
 "$_VCS_FHS_ENV" -c "./$_emu_name $_EXTRA_ARGS" &> >(tee vcs-emu-journal.log)
 
+_status=0
+if ! _run_summary_status "$@"; then
+  _status=1
+fi
+
 if ((${DATA_ONLY:-0})); then
   rm -f "./$_emu_name"
 fi
@@ -40,3 +48,4 @@ set -e _emu_name _daidir
 popd >/dev/null
 
 echo "VCS emulator finished, result saved in $_SDRAM_SIM_RESULT_DIR/result"
+exit $_status
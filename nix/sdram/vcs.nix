@@ -3,6 +3,7 @@
 
 { lib
 , bash
+, jq
 , stdenv
 , rtl
 , dpi-lib
@@ -91,6 +92,8 @@ stdenv.mkDerivation (finalAttr: {
     substitute ${./vcs-wrapper.sh} $out/bin/${binName} \
       --subst-var-by shell "${bash}/bin/bash" \
       --subst-var-by dateBin "$(command -v date)" \
+      --subst-var-by jqBin "${lib.getExe jq}" \
+      --subst-var-by runSummary "${./run-summary.sh}" \
       --subst-var-by vcsSimBin "$out/lib/${binName}" \
       --subst-var-by vcsSimDaidir "$out/lib/${binName}.daidir" \
       --subst-var-by vcsFhsEnv "${vcs-fhs-env}/bin/vcs-fhs-env"
@@ -0,0 +1,14 @@
+#!@shell@
+
+if ((${VERBOSE:-0})); then
+  set -x
+fi
+
+_JQ_BIN=@jqBin@
+_VERILATED_BIN=@verilatedBin@
+
+source @runSummary@
+
+"$_VERILATED_BIN" "$@"
+
+_run_summary_status "$@"
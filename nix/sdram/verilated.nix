@@ -1,7 +1,7 @@
 # SPDX-License-Identifier: Apache-2.0
 # SPDX-FileCopyrightText: 2024 Jiuyang Liu <liu@jiuyang.me>
 
-{ lib, stdenv, rtl, verilator, zlib, dpi-lib, thread-num ? 8 }:
+{ lib, stdenv, bash, jq, rtl, verilator, zlib, dpi-lib, thread-num ? 8 }:
 let vName = "V${rtl.target}";
 in stdenv.mkDerivation {
   name = "verilated";
@@ -54,7 +54,15 @@ in stdenv.mkDerivation {
     mkdir -p $out/{include,lib,bin}
     cp *.h $out/include
     cp *.a $out/lib
-    cp ${vName} $out/bin
+    cp ${vName} $out/lib
+
+    # exits 1 unless the run summary says PASS
+    substitute ${./verilated-wrapper.sh} $out/bin/${vName} \
+      --subst-var-by shell "${bash}/bin/bash" \
+      --subst-var-by jqBin "${lib.getExe jq}" \
+      --subst-var-by runSummary "${./run-summary.sh}" \
+      --subst-var-by verilatedBin "$out/lib/${vName}"
+    chmod +x $out/bin/${vName}
 
     runHook postInstall
   '';
@@ -5,7 +5,11 @@ import chisel3.experimental.hierarchy.{instantiable, public, Instance, Instantia
 import chisel3.experimental.{SerializableModule, SerializableModuleParameter}
 import chisel3.properties.{Class, Property}
 import chisel3.util.{Counter, HasExtModuleInline}
-import chisel3.util.circt.dpi.{RawClockedNonVoidFunctionCall, RawUnclockedNonVoidFunctionCall}
+import chisel3.util.circt.dpi.{
+  RawClockedNonVoidFunctionCall,
+  RawClockedVoidFunctionCall,
+  RawUnclockedNonVoidFunctionCall
+}
 import chisel3.experimental.dataview.DataViewable
 
 import scala.util.chaining._
@@ -20,7 +24,8 @@ object SDRAMControllerTestBenchParameter {
 case class SDRAMControllerTestBenchParameter(
   sdramControllerParameter: SDRAMControllerParameter,
   testVerbatimParameter:    TestVerbatimParameter,
-  timeout:                  Int)
+  timeout:                  Int,
+  useDeviceModel:           Boolean = false)
     extends SerializableModuleParameter
 
 class W9825G6KHInterface extends Bundle {
@@ -40,6 +45,34 @@ class W9825G6KHInterface extends Bundle {
 @public
 class W9825G6KH extends FixedIOExtModule(new W9825G6KHInterface)
 
+object SDRAMDeviceModel {
+
+  /** Hand the pins of chip select `csIndex` to `sdram_tick` at every rising edge, where they are checked against the
+    * SDRAM timing. Returns what the device model drives on DQ, zero if there is none.
+    */
+  def tick(pins: W9825G6KHInterface, csIndex: Int): UInt =
+    RawClockedNonVoidFunctionCall("sdram_tick", UInt(32.W))(
+      pins.Clk,
+      true.B,
+      csIndex.U(8.W),
+      pins.Addr.asTypeOf(UInt(32.W)),
+      pins.Bs.asTypeOf(UInt(8.W)),
+      pins.Cke.asTypeOf(UInt(8.W)),
+      pins.Cs_n.asTypeOf(UInt(8.W)),
+      pins.Ras_n.asTypeOf(UInt(8.W)),
+      pins.Cas_n.asTypeOf(UInt(8.W)),
+      pins.We_n.asTypeOf(UInt(8.W)),
+      pins.Dqm.asTypeOf(UInt(8.W)),
+      pins.Dq_i
+    )
+}
+
+/** Drop-in replacement of [[W9825G6KH]] backed by the device model in sdramemu, clocked through `sdram_tick`. */
+@public
+class SDRAMDeviceModel(csIndex: Int) extends FixedIORawModule(new W9825G6KHInterface) {
+  io.Dq_o := SDRAMDeviceModel.tick(io, csIndex)
+}
+
 class SDRAMControllerTestBench(val parameter: SDRAMControllerTestBenchParameter)
     extends RawModule
     with SerializableModule[SDRAMControllerTestBenchParameter]
@@ -82,25 +115,67 @@ class SDRAMControllerTestBench(val parameter: SDRAMControllerTestBenchParameter)
     stop(cf"""{"event":"SimulationStop","reason": ${watchdogCode}}\n""")
   }
 
+  // Hand every channel of the DUT's AXI port to `axi_monitor_tick` at every rising edge, where it is checked against
+  // the AXI4 protocol.
+  val axi = dut.io.axi
+  RawClockedVoidFunctionCall("axi_monitor_tick")(
+    agent.io.clock,
+    true.B,
+    implicitReset.asBool.asTypeOf(UInt(8.W)),
+    axi.aw.valid.asTypeOf(UInt(8.W)),
+    axi.aw.ready.asTypeOf(UInt(8.W)),
+    axi.aw.bits.id.asTypeOf(UInt(8.W)),
+    axi.aw.bits.addr.asTypeOf(UInt(32.W)),
+    axi.aw.bits.len.asTypeOf(UInt(8.W)),
+    axi.aw.bits.size.asTypeOf(UInt(8.W)),
+    axi.aw.bits.burst.asTypeOf(UInt(8.W)),
+    axi.w.valid.asTypeOf(UInt(8.W)),
+    axi.w.ready.asTypeOf(UInt(8.W)),
+    axi.w.bits.data.asTypeOf(UInt(32.W)),
+    axi.w.bits.strb.asTypeOf(UInt(8.W)),
+    axi.w.bits.last.asTypeOf(UInt(8.W)),
+    axi.b.valid.asTypeOf(UInt(8.W)),
+    axi.b.ready.asTypeOf(UInt(8.W)),
+    axi.b.bits.id.asTypeOf(UInt(8.W)),
+    axi.b.bits.resp.asTypeOf(UInt(8.W)),
+    axi.ar.valid.asTypeOf(UInt(8.W)),
+    axi.ar.ready.asTypeOf(UInt(8.W)),
+    axi.ar.bits.id.asTypeOf(UInt(8.W)),
+    axi.ar.bits.addr.asTypeOf(UInt(32.W)),
+    axi.ar.bits.len.asTypeOf(UInt(8.W)),
+    axi.ar.bits.size.asTypeOf(UInt(8.W)),
+    axi.ar.bits.burst.asTypeOf(UInt(8.W)),
+    axi.r.valid.asTypeOf(UInt(8.W)),
+    axi.r.ready.asTypeOf(UInt(8.W)),
+    axi.r.bits.id.asTypeOf(UInt(8.W)),
+    axi.r.bits.data.asTypeOf(UInt(32.W)),
+    axi.r.bits.resp.asTypeOf(UInt(8.W)),
+    axi.r.bits.last.asTypeOf(UInt(8.W))
+  )
+
   /** SDRAM <-> DUT */
-  Seq
-    .fill(parameter.sdramControllerParameter.sdramParameter.csWidth) {
-      Instantiate(new W9825G6KH).io
-    }
-    .zipWithIndex
-    .foreach { case (bundle, index) =>
-      bundle.Addr := dut.io.sdram.a
-      bundle.Bs := dut.io.sdram.ba
-      bundle.Cke := dut.io.sdram.cke(index).asBool
-      bundle.Clk := dut.io.sdram.ck(index)
-      bundle.Cs_n := dut.io.sdram.cs(index).asBool
-      bundle.Dq_i := dut.io.sdram.dqo
-      dut.io.sdram.dqi := bundle.Dq_o
-      bundle.Dqm := dut.io.sdram.dqm
-      bundle.Ras_n := dut.io.sdram.ras
-      bundle.We_n := dut.io.sdram.we
-      bundle.Cas_n := dut.io.sdram.cas
+  val sdrams = Seq
+    .tabulate(parameter.sdramControllerParameter.sdramParameter.csWidth) { index =>
+      if (parameter.useDeviceModel) Instantiate(new SDRAMDeviceModel(index)).io
+      else Instantiate(new W9825G6KH).io
     }
+  sdrams.zipWithIndex.foreach { case (bundle, index) =>
+    bundle.Addr := dut.io.sdram.a
+    bundle.Bs := dut.io.sdram.ba
+    bundle.Cke := dut.io.sdram.cke(index).asBool
+    bundle.Clk := dut.io.sdram.ck(index)
+    bundle.Cs_n := dut.io.sdram.cs(index).asBool
+    bundle.Dq_i := dut.io.sdram.dqo
+    bundle.Dqm := dut.io.sdram.dqm
+    bundle.Ras_n := dut.io.sdram.ras
+    bundle.We_n := dut.io.sdram.we
+    bundle.Cas_n := dut.io.sdram.cas
+    // the vendor model is not checked by sdramemu otherwise
+    if (!parameter.useDeviceModel) dontTouch(SDRAMDeviceModel.tick(bundle, index))
+  }
+  // The device models drive zero while they do not output read data, so the chip selects share DQ by OR. Whether the
+  // vendor model does is not known, only chip select 0 is read from it.
+  dut.io.sdram.dqi := (if (parameter.useDeviceModel) sdrams.map(_.Dq_o).reduce(_ | _) else sdrams.head.Dq_o)
 
   override protected def implicitClock: Clock = verbatim.io.clock
 
@@ -11,11 +11,8 @@ pub struct CommonArgs {
   /// Log level: trace, debug, info, warn, error
   pub log_level: String,
 
-  /// vlen config
-  pub vlen: u32,
-
-  /// dlen config
-  pub dlen: u32,
+  /// Path to the testbench json config
+  pub rtl_config: String,
 
 }
 
@@ -42,8 +39,14 @@ impl CommonArgs {
     pub fn from_plusargs(matcher: &PlusArgMatcher) -> Self {
         Self {
             log_level: matcher.try_match("log-level").unwrap_or("info").into(),
-            vlen:matcher.try_match("vlen").unwrap_or("32").parse().unwrap(),
-            dlen:matcher.try_match("dlen").unwrap_or("32").parse().unwrap(),
+            rtl_config: matcher
+                .try_match("rtl-config")
+                .or(option_env!("RTL_CONFIG"))
+                .unwrap_or_else(|| {
+                    tracing::error!("required plusarg '+rtl-config=' not found");
+                    panic!("failed to match '+rtl-config='");
+                })
+                .into(),
         }
     }
 }
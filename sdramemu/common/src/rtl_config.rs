@@ -1,20 +1,141 @@
-pub struct RTLConfig {
-  pub vlen: u32,
-  pub dlen: u32,
+use anyhow::{ensure, Context, Result};
+use serde::Deserialize;
+use std::path::Path;
+
+/// Mirror of `AXI4BundleParameter` as serialized by the elaborator.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AxiParameter {
+  pub id_width: u32,
+  pub data_width: u32,
+  pub addr_width: u32,
+  pub user_req_width: u32,
+  pub user_data_width: u32,
+  pub user_resp_width: u32,
+  #[serde(rename = "hasAW")]
+  pub has_aw: bool,
+  pub has_w: bool,
+  pub has_b: bool,
+  #[serde(rename = "hasAR")]
+  pub has_ar: bool,
+  pub has_r: bool,
+  pub support_id: bool,
+  pub support_region: bool,
+  pub support_len: bool,
+  pub support_size: bool,
+  pub support_burst: bool,
+  pub support_lock: bool,
+  pub support_cache: bool,
+  pub support_qos: bool,
+  pub support_strb: bool,
+  pub support_resp: bool,
+  pub support_prot: bool,
 }
 
-// TODO: read from json
+impl AxiParameter {
+  pub fn aw_user_width(&self) -> u32 {
+    self.user_req_width
+  }
+
+  pub fn ar_user_width(&self) -> u32 {
+    self.user_req_width
+  }
+
+  pub fn w_user_width(&self) -> u32 {
+    self.user_data_width
+  }
+
+  /// AXI data bus width in bytes, i.e. the number of byte lanes.
+  pub fn bus_bytes(&self) -> u32 {
+    self.data_width / 8
+  }
+
+  /// Largest legal AxSIZE for this bus.
+  pub fn max_size(&self) -> u8 {
+    self.bus_bytes().trailing_zeros() as u8
+  }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SdramParameter {
+  pub data_width: u32,
+  pub cs_width: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SdramControllerParameter {
+  pub axi_parameter: AxiParameter,
+  pub sdram_parameter: SdramParameter,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestVerbatimParameter {
+  pub use_async_reset: bool,
+  pub init_function_name: String,
+  pub dump_function_name: String,
+  pub clock_flip_tick: u64,
+  pub reset_flip_tick: u64,
+}
+
+/// Testbench configuration, i.e. `configs/SDRAMControllerTestBenchMain.json`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RTLConfig {
+  pub sdram_controller_parameter: SdramControllerParameter,
+  pub test_verbatim_parameter: TestVerbatimParameter,
+  pub timeout: u64,
+  /// Drive the SDRAM pins from the `sdramemu` device model instead of the
+  /// vendor Verilog model.
+  #[serde(default)]
+  pub use_device_model: bool,
+}
 
 impl RTLConfig {
-  pub fn xlen(&self) -> u32 {
-    32 // TODO: configurable
+  pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+    let path = path.as_ref();
+    let content = std::fs::read_to_string(path)
+      .with_context(|| format!("failed to read rtl config `{}`", path.display()))?;
+    let config: Self = serde_json::from_str(&content)
+      .with_context(|| format!("failed to parse rtl config `{}`", path.display()))?;
+    config.validate()?;
+    Ok(config)
+  }
+
+  pub fn axi(&self) -> &AxiParameter {
+    &self.sdram_controller_parameter.axi_parameter
   }
 
-  pub fn vlen_in_bytes(&self) -> u32 {
-    self.vlen / 8
+  pub fn sdram(&self) -> &SdramParameter {
+    &self.sdram_controller_parameter.sdram_parameter
   }
 
-  pub fn lane_num(&self) -> u32 {
-    self.dlen / self.xlen()
+  // the limits below come from the AXI4MasterAgent DPI interface, not from AXI itself
+  fn validate(&self) -> Result<()> {
+    let axi = self.axi();
+    ensure!(
+      axi.data_width >= 8 && axi.data_width.is_power_of_two(),
+      "axi dataWidth must be a power of two and at least 8, got {}",
+      axi.data_width
+    );
+    ensure!(
+      axi.data_width <= 32,
+      "axi dataWidth {} is wider than the 32-bit read probe",
+      axi.data_width
+    );
+    ensure!(
+      axi.id_width <= 8,
+      "axi idWidth {} is wider than the 8-bit response probe",
+      axi.id_width
+    );
+    ensure!(
+      axi.addr_width <= 32,
+      "axi addrWidth {} is not supported, at most 32",
+      axi.addr_width
+    );
+    ensure!(self.sdram().cs_width > 0, "sdram csWidth must not be zero");
+    Ok(())
   }
 }
@@ -0,0 +1,135 @@
+//! The AXI4 side of the controller: the signals of its slave port as sampled
+//! at every rising clock edge, and a monitor checking them against the rules
+//! of the AMBA AXI4 protocol.
+
+mod monitor;
+
+pub use monitor::{ProtocolMonitor, ProtocolRule, ProtocolViolation};
+
+/// One of the five channels of the port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Aw,
+    W,
+    B,
+    Ar,
+    R,
+}
+
+impl Channel {
+    /// Whether the master drives VALID and the payload, the slave otherwise.
+    pub fn from_master(self) -> bool {
+        matches!(self, Self::Aw | Self::W | Self::Ar)
+    }
+
+    /// Signal name prefix, as in `AWVALID`.
+    pub fn prefix(self) -> &'static str {
+        match self {
+            Self::Aw => "AW",
+            Self::W => "W",
+            Self::B => "B",
+            Self::Ar => "AR",
+            Self::R => "R",
+        }
+    }
+}
+
+/// AW or AR.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AddressChannel {
+    pub valid: bool,
+    pub ready: bool,
+    pub id: u8,
+    pub addr: u32,
+    pub len: u8,
+    pub size: u8,
+    pub burst: u8,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WriteDataChannel {
+    pub valid: bool,
+    pub ready: bool,
+    pub data: u32,
+    pub strb: u8,
+    pub last: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WriteResponseChannel {
+    pub valid: bool,
+    pub ready: bool,
+    pub id: u8,
+    pub resp: u8,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReadDataChannel {
+    pub valid: bool,
+    pub ready: bool,
+    pub id: u8,
+    pub data: u32,
+    pub resp: u8,
+    pub last: bool,
+}
+
+/// Signals of the port, as sampled at a rising clock edge.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AxiSignals {
+    pub reset: bool,
+    pub aw: AddressChannel,
+    pub w: WriteDataChannel,
+    pub b: WriteResponseChannel,
+    pub ar: AddressChannel,
+    pub r: ReadDataChannel,
+}
+
+impl AxiSignals {
+    /// VALID and READY of `channel`.
+    pub fn handshake(&self, channel: Channel) -> (bool, bool) {
+        match channel {
+            Channel::Aw => (self.aw.valid, self.aw.ready),
+            Channel::W => (self.w.valid, self.w.ready),
+            Channel::B => (self.b.valid, self.b.ready),
+            Channel::Ar => (self.ar.valid, self.ar.ready),
+            Channel::R => (self.r.valid, self.r.ready),
+        }
+    }
+
+    /// Payload signals of `channel` that differ from `other`, by name
+    /// without the channel prefix, as in `ADDR`.
+    pub fn changed(&self, other: &Self, channel: Channel) -> Vec<&'static str> {
+        fn address(a: &AddressChannel, b: &AddressChannel) -> Vec<(&'static str, bool)> {
+            vec![
+                ("ID", a.id != b.id),
+                ("ADDR", a.addr != b.addr),
+                ("LEN", a.len != b.len),
+                ("SIZE", a.size != b.size),
+                ("BURST", a.burst != b.burst),
+            ]
+        }
+        let fields = match channel {
+            Channel::Aw => address(&self.aw, &other.aw),
+            Channel::Ar => address(&self.ar, &other.ar),
+            Channel::W => vec![
+                ("DATA", self.w.data != other.w.data),
+                ("STRB", self.w.strb != other.w.strb),
+                ("LAST", self.w.last != other.w.last),
+            ],
+            Channel::B => vec![
+                ("ID", self.b.id != other.b.id),
+                ("RESP", self.b.resp != other.b.resp),
+            ],
+            Channel::R => vec![
+                ("ID", self.r.id != other.r.id),
+                ("DATA", self.r.data != other.r.data),
+                ("RESP", self.r.resp != other.r.resp),
+                ("LAST", self.r.last != other.r.last),
+            ],
+        };
+        fields
+            .into_iter()
+            .filter_map(|(name, changed)| changed.then_some(name))
+            .collect()
+    }
+}
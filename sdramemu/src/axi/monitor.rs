@@ -0,0 +1,591 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+use super::{AddressChannel, AxiSignals, Channel};
+use crate::dpi::{beat_addresses, beat_lanes};
+use crate::profile::AXI_BOUNDARY;
+
+const CHANNELS: [Channel; 5] = [Channel::Aw, Channel::W, Channel::B, Channel::Ar, Channel::R];
+
+/// A rule of the AMBA AXI4 protocol. Displayed as the ID of the matching
+/// assertion of ARM's AXI4 protocol checker, e.g. `AXI4_ERRS_RDATA_NUM`,
+/// `ERRM` ones are broken by the master and `ERRS` ones by the slave.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtocolRule {
+    /// VALID was deasserted before its handshake.
+    ValidStable(Channel),
+    /// A payload signal, named without the channel prefix, changed while
+    /// VALID waited for READY.
+    Stable(Channel, &'static str),
+    /// VALID was asserted in the first cycle after reset.
+    ValidReset(Channel),
+    /// The reserved burst type.
+    Burst(Channel),
+    /// A WRAP burst not of 2, 4, 8 or 16 beats.
+    LenWrap(Channel),
+    /// A FIXED burst longer than 16 beats.
+    LenFixed(Channel),
+    /// A transfer wider than the data bus.
+    Size(Channel),
+    /// A WRAP burst starting at an address unaligned to its transfer size.
+    AddrWrapAlign(Channel),
+    /// An INCR burst crossing a 4KB boundary.
+    AddrBoundary(Channel),
+    /// WLAST on another beat than the last of the burst.
+    WdataNum,
+    /// Write strobes on byte lanes the beat does not occupy.
+    Wstrb,
+    /// BVALID before the address handshake of a write with its ID.
+    BrespAw,
+    /// BVALID before the last data handshake of its write.
+    BrespWlast,
+    /// RVALID with an ID no read in flight has.
+    Rid,
+    /// RLAST on another beat than the last of the burst.
+    RdataNum,
+}
+
+impl fmt::Display for ProtocolRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (channel, name) = match *self {
+            Self::ValidStable(channel) => (channel, format!("{}VALID_STABLE", channel.prefix())),
+            Self::Stable(channel, signal) => {
+                (channel, format!("{}{signal}_STABLE", channel.prefix()))
+            }
+            Self::ValidReset(channel) => (channel, format!("{}VALID_RESET", channel.prefix())),
+            Self::Burst(channel) => (channel, format!("{}BURST", channel.prefix())),
+            Self::LenWrap(channel) => (channel, format!("{}LEN_WRAP", channel.prefix())),
+            Self::LenFixed(channel) => (channel, format!("{}LEN_FIXED", channel.prefix())),
+            Self::Size(channel) => (channel, format!("{}SIZE", channel.prefix())),
+            Self::AddrWrapAlign(channel) => {
+                (channel, format!("{}ADDR_WRAP_ALIGN", channel.prefix()))
+            }
+            Self::AddrBoundary(channel) => (channel, format!("{}ADDR_BOUNDARY", channel.prefix())),
+            Self::WdataNum => (Channel::W, "WDATA_NUM".to_owned()),
+            Self::Wstrb => (Channel::W, "WSTRB".to_owned()),
+            Self::BrespAw => (Channel::B, "BRESP_AW".to_owned()),
+            Self::BrespWlast => (Channel::B, "BRESP_WLAST".to_owned()),
+            Self::Rid => (Channel::R, "RID".to_owned()),
+            Self::RdataNum => (Channel::R, "RDATA_NUM".to_owned()),
+        };
+        let side = if channel.from_master() {
+            "ERRM"
+        } else {
+            "ERRS"
+        };
+        write!(f, "AXI4_{side}_{name}")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtocolViolation {
+    pub rule: ProtocolRule,
+    /// What was seen on the port.
+    pub detail: String,
+}
+
+impl fmt::Display for ProtocolViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} violated: {}", self.rule, self.detail)
+    }
+}
+
+/// A write whose address handshake is done.
+#[derive(Debug, Clone)]
+struct Write {
+    id: u8,
+    beats: u32,
+    /// Byte lanes of every beat, `None` if the burst is illegal.
+    lanes: Option<Vec<u8>>,
+}
+
+/// Checks the slave port of the controller against the AXI4 protocol,
+/// clocked once per rising edge of the AXI clock. Transactions are tracked
+/// from their handshakes, as AXI4 orders them: write data in the order of
+/// the write addresses, responses in order within an ID.
+pub struct ProtocolMonitor {
+    bus_bytes: u32,
+    /// Signals of the previous cycle, `None` in and right after reset.
+    last: Option<AxiSignals>,
+    /// Whether the previous cycle was in reset.
+    in_reset: bool,
+    /// Writes waiting for data, oldest first, and the data beats of the
+    /// oldest accepted so far.
+    writes: VecDeque<Write>,
+    write_beat: u32,
+    /// Beats of write data bursts completed ahead of their address.
+    early_data: VecDeque<u32>,
+    /// Writes waiting for their response, by ID.
+    responses: HashMap<u8, u32>,
+    /// Beats of the reads in flight by ID, oldest first.
+    reads: HashMap<u8, VecDeque<u32>>,
+    /// Beats of the oldest read of each ID received so far.
+    read_beats: HashMap<u8, u32>,
+}
+
+impl ProtocolMonitor {
+    pub fn new(bus_bytes: u32) -> Self {
+        Self {
+            bus_bytes,
+            last: None,
+            in_reset: false,
+            writes: VecDeque::new(),
+            write_beat: 0,
+            early_data: VecDeque::new(),
+            responses: HashMap::new(),
+            reads: HashMap::new(),
+            read_beats: HashMap::new(),
+        }
+    }
+
+    /// Check the signals sampled at a rising edge.
+    pub fn tick(&mut self, signals: &AxiSignals) -> Vec<ProtocolViolation> {
+        let mut violations = Vec::new();
+        if signals.reset {
+            *self = Self::new(self.bus_bytes);
+            self.in_reset = true;
+            return violations;
+        }
+        let mut violate =
+            |rule, detail: String| violations.push(ProtocolViolation { rule, detail });
+
+        if self.in_reset {
+            for channel in CHANNELS {
+                if signals.handshake(channel).0 {
+                    violate(
+                        ProtocolRule::ValidReset(channel),
+                        format!("{}VALID high right after reset", channel.prefix()),
+                    );
+                }
+            }
+            self.in_reset = false;
+        }
+
+        // a transfer is offered anew unless it waited for READY last cycle
+        let mut waited = [false; CHANNELS.len()];
+        if let Some(last) = &self.last {
+            for (index, channel) in CHANNELS.into_iter().enumerate() {
+                let prefix = channel.prefix();
+                if last.handshake(channel) != (true, false) {
+                    continue;
+                }
+                waited[index] = true;
+                if !signals.handshake(channel).0 {
+                    violate(
+                        ProtocolRule::ValidStable(channel),
+                        format!("{prefix}VALID dropped before {prefix}READY"),
+                    );
+                    continue;
+                }
+                for signal in signals.changed(last, channel) {
+                    violate(
+                        ProtocolRule::Stable(channel, signal),
+                        format!(
+                            "{prefix}{signal} changed while {prefix}VALID waited for {prefix}READY"
+                        ),
+                    );
+                }
+            }
+        }
+        let [_, _, b_waited, _, r_waited] = waited;
+
+        // responses first, one in the same cycle as its request is early
+        let b = signals.b;
+        if b.valid && !b_waited && self.responses.get(&b.id).map_or(true, |&count| count == 0) {
+            if self.writes.iter().any(|write| write.id == b.id) {
+                violate(
+                    ProtocolRule::BrespWlast,
+                    format!(
+                        "BVALID for write id 0x{:02x} before its last data handshake",
+                        b.id
+                    ),
+                );
+            } else {
+                violate(
+                    ProtocolRule::BrespAw,
+                    format!(
+                        "BVALID with BID 0x{:02x} and no write of that ID addressed",
+                        b.id
+                    ),
+                );
+            }
+        }
+        if b.valid && b.ready {
+            if let Some(count) = self.responses.get_mut(&b.id).filter(|count| **count > 0) {
+                *count -= 1;
+            }
+        }
+
+        let r = signals.r;
+        let pending = self
+            .reads
+            .get(&r.id)
+            .and_then(|reads| reads.front())
+            .copied();
+        if r.valid && !r_waited && pending.is_none() {
+            violate(
+                ProtocolRule::Rid,
+                format!(
+                    "RVALID with RID 0x{:02x} and no read of that ID in flight",
+                    r.id
+                ),
+            );
+        }
+        if let Some(beats) = pending.filter(|_| r.valid && r.ready) {
+            let received = self.read_beats.entry(r.id).or_default();
+            let beat = *received;
+            *received += 1;
+            let last_beat = beat + 1 == beats;
+            if r.last != last_beat {
+                violate(
+                    ProtocolRule::RdataNum,
+                    if r.last {
+                        format!("RLAST on beat {beat} of {beats} of read id 0x{:02x}", r.id)
+                    } else {
+                        format!(
+                            "no RLAST on the last of {beats} beats of read id 0x{:02x}",
+                            r.id
+                        )
+                    },
+                );
+            }
+            if r.last || last_beat {
+                self.reads.get_mut(&r.id).unwrap().pop_front();
+                self.read_beats.remove(&r.id);
+            }
+        }
+
+        let aw = signals.aw;
+        if aw.valid && aw.ready {
+            let legal = self.check_address(Channel::Aw, &aw, &mut violate);
+            let write = Write {
+                id: aw.id,
+                beats: aw.len as u32 + 1,
+                lanes: legal.then(|| {
+                    beat_addresses(aw.addr, aw.burst, aw.len, aw.size)
+                        .into_iter()
+                        .map(|addr| beat_lanes(addr, aw.size, self.bus_bytes))
+                        .collect()
+                }),
+            };
+            match self.early_data.pop_front() {
+                Some(beats) => {
+                    if beats != write.beats {
+                        violate(
+                            ProtocolRule::WdataNum,
+                            format!(
+                                "{beats} data beat(s) ahead of write id 0x{:02x} of {} beats",
+                                write.id, write.beats
+                            ),
+                        );
+                    }
+                    *self.responses.entry(write.id).or_default() += 1;
+                }
+                None => self.writes.push_back(write),
+            }
+        }
+
+        let w = signals.w;
+        if w.valid && w.ready {
+            let beat = self.write_beat;
+            self.write_beat += 1;
+            match self.writes.front() {
+                Some(write) => {
+                    let lanes = write
+                        .lanes
+                        .as_ref()
+                        .map_or(0xff, |lanes| lanes[beat as usize]);
+                    if w.strb & !lanes != 0 {
+                        violate(
+                            ProtocolRule::Wstrb,
+                            format!(
+                                "WSTRB 0b{:04b} on beat {beat} of write id 0x{:02x}, only lanes 0b{lanes:04b} are active",
+                                w.strb, write.id
+                            ),
+                        );
+                    }
+                    let last_beat = beat + 1 == write.beats;
+                    if w.last != last_beat {
+                        violate(
+                            ProtocolRule::WdataNum,
+                            if w.last {
+                                format!(
+                                    "WLAST on beat {beat} of {} of write id 0x{:02x}",
+                                    write.beats, write.id
+                                )
+                            } else {
+                                format!(
+                                    "no WLAST on the last of {} beats of write id 0x{:02x}",
+                                    write.beats, write.id
+                                )
+                            },
+                        );
+                    }
+                    if w.last || last_beat {
+                        let write = self.writes.pop_front().unwrap();
+                        *self.responses.entry(write.id).or_default() += 1;
+                        self.write_beat = 0;
+                    }
+                }
+                None if w.last => {
+                    self.early_data.push_back(beat + 1);
+                    self.write_beat = 0;
+                }
+                None => {}
+            }
+        }
+
+        let ar = signals.ar;
+        if ar.valid && ar.ready {
+            self.check_address(Channel::Ar, &ar, &mut violate);
+            self.reads
+                .entry(ar.id)
+                .or_default()
+                .push_back(ar.len as u32 + 1);
+        }
+
+        self.last = Some(*signals);
+        violations
+    }
+
+    /// Check the burst of an address handshake, returns whether its beats
+    /// can be worked out.
+    fn check_address(
+        &self,
+        channel: Channel,
+        address: &AddressChannel,
+        violate: &mut impl FnMut(ProtocolRule, String),
+    ) -> bool {
+        let prefix = channel.prefix();
+        let id = address.id;
+        let beats = address.len as u32 + 1;
+        let bytes = 1u32 << address.size;
+        let mut legal = true;
+        if address.burst == 3 {
+            violate(
+                ProtocolRule::Burst(channel),
+                format!("{prefix}BURST 0b11 is reserved, id 0x{id:02x}"),
+            );
+            legal = false;
+        }
+        if bytes > self.bus_bytes {
+            violate(
+                ProtocolRule::Size(channel),
+                format!(
+                    "{prefix}SIZE {} is {bytes} bytes, wider than the {} byte bus, id 0x{id:02x}",
+                    address.size, self.bus_bytes
+                ),
+            );
+            legal = false;
+        }
+        if address.burst == 0 && beats > 16 {
+            violate(
+                ProtocolRule::LenFixed(channel),
+                format!("FIXED burst of {beats} beats, id 0x{id:02x}"),
+            );
+        }
+        if address.burst == 2 {
+            if !matches!(beats, 2 | 4 | 8 | 16) {
+                violate(
+                    ProtocolRule::LenWrap(channel),
+                    format!("WRAP burst of {beats} beats, id 0x{id:02x}"),
+                );
+            }
+            if address.addr % bytes != 0 {
+                violate(
+                    ProtocolRule::AddrWrapAlign(channel),
+                    format!(
+                        "WRAP burst from 0x{:08x}, unaligned to {bytes} bytes, id 0x{id:02x}",
+                        address.addr
+                    ),
+                );
+            }
+        }
+        if address.burst == 1 {
+            let last = (address.addr / bytes * bytes) as u64 + (beats * bytes) as u64 - 1;
+            if address.addr / AXI_BOUNDARY != (last / AXI_BOUNDARY as u64) as u32 {
+                violate(
+                    ProtocolRule::AddrBoundary(channel),
+                    format!(
+                        "INCR burst 0x{:08x}..=0x{last:08x} crosses a 4KB boundary, id 0x{id:02x}",
+                        address.addr
+                    ),
+                );
+            }
+        }
+        legal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::axi::{ReadDataChannel, WriteDataChannel, WriteResponseChannel};
+
+    /// Rules broken over `cycles`, run right after a cycle in reset.
+    fn violations(cycles: &[AxiSignals]) -> Vec<ProtocolViolation> {
+        let mut monitor = ProtocolMonitor::new(4);
+        monitor.tick(&AxiSignals {
+            reset: true,
+            ..AxiSignals::default()
+        });
+        monitor.tick(&AxiSignals::default());
+        cycles
+            .iter()
+            .flat_map(|signals| monitor.tick(signals))
+            .collect()
+    }
+
+    fn address(id: u8, addr: u32, len: u8, burst: u8) -> AddressChannel {
+        AddressChannel {
+            valid: true,
+            ready: true,
+            id,
+            addr,
+            len,
+            size: 2,
+            burst,
+        }
+    }
+
+    fn write_data(last: bool) -> WriteDataChannel {
+        WriteDataChannel {
+            valid: true,
+            ready: true,
+            data: 0,
+            strb: 0xf,
+            last,
+        }
+    }
+
+    fn read_data(id: u8, last: bool) -> ReadDataChannel {
+        ReadDataChannel {
+            valid: true,
+            ready: true,
+            id,
+            data: 0,
+            resp: 0,
+            last,
+        }
+    }
+
+    #[test]
+    fn write_and_read_pass() {
+        let response = WriteResponseChannel {
+            valid: true,
+            ready: true,
+            id: 1,
+            resp: 0,
+        };
+        let found = violations(&[
+            AxiSignals {
+                aw: address(1, 0x100, 1, 1),
+                w: write_data(false),
+                ..AxiSignals::default()
+            },
+            AxiSignals {
+                w: write_data(true),
+                ar: address(2, 0x100, 1, 1),
+                ..AxiSignals::default()
+            },
+            AxiSignals {
+                b: response,
+                r: read_data(2, false),
+                ..AxiSignals::default()
+            },
+            AxiSignals {
+                r: read_data(2, true),
+                ..AxiSignals::default()
+            },
+        ]);
+        assert_eq!(found, []);
+    }
+
+    #[test]
+    fn rlast_on_wrong_beat() {
+        let found = violations(&[
+            AxiSignals {
+                ar: address(2, 0x100, 3, 1),
+                ..AxiSignals::default()
+            },
+            AxiSignals {
+                r: read_data(2, false),
+                ..AxiSignals::default()
+            },
+            AxiSignals {
+                r: read_data(2, true),
+                ..AxiSignals::default()
+            },
+        ]);
+        assert_eq!(
+            found,
+            [ProtocolViolation {
+                rule: ProtocolRule::RdataNum,
+                detail: "RLAST on beat 1 of 4 of read id 0x02".to_owned(),
+            }]
+        );
+        assert_eq!(found[0].rule.to_string(), "AXI4_ERRS_RDATA_NUM");
+    }
+
+    #[test]
+    fn bresp_before_wlast() {
+        let found = violations(&[
+            AxiSignals {
+                aw: address(1, 0x100, 1, 1),
+                w: write_data(false),
+                ..AxiSignals::default()
+            },
+            AxiSignals {
+                b: WriteResponseChannel {
+                    valid: true,
+                    ready: true,
+                    id: 1,
+                    resp: 0,
+                },
+                ..AxiSignals::default()
+            },
+        ]);
+        let rules: Vec<_> = found.iter().map(|violation| violation.rule).collect();
+        assert_eq!(rules, [ProtocolRule::BrespWlast]);
+        assert_eq!(found[0].rule.to_string(), "AXI4_ERRS_BRESP_WLAST");
+    }
+
+    #[test]
+    fn payload_changed_while_valid_waits() {
+        let waiting = ReadDataChannel {
+            ready: false,
+            ..read_data(2, true)
+        };
+        let found = violations(&[
+            AxiSignals {
+                ar: address(2, 0x100, 0, 1),
+                ..AxiSignals::default()
+            },
+            AxiSignals {
+                r: waiting,
+                ..AxiSignals::default()
+            },
+            AxiSignals {
+                r: ReadDataChannel {
+                    data: 0x1234,
+                    ..waiting
+                },
+                ..AxiSignals::default()
+            },
+        ]);
+        let rules: Vec<_> = found.iter().map(|violation| violation.rule).collect();
+        assert_eq!(rules, [ProtocolRule::Stable(Channel::R, "DATA")]);
+        assert_eq!(found[0].rule.to_string(), "AXI4_ERRS_RDATA_STABLE");
+    }
+
+    #[test]
+    fn fixed_burst_over_16_beats() {
+        let found = violations(&[AxiSignals {
+            ar: address(3, 0x100, 16, 0),
+            ..AxiSignals::default()
+        }]);
+        let rules: Vec<_> = found.iter().map(|violation| violation.rule).collect();
+        assert_eq!(rules, [ProtocolRule::LenFixed(Channel::Ar)]);
+        assert_eq!(found[0].rule.to_string(), "AXI4_ERRM_ARLEN_FIXED");
+    }
+}
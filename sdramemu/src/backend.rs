@@ -0,0 +1,127 @@
+//! What `Driver` needs from the simulator running it.
+//!
+//! `DpiBackend` talks to a VCS or Verilator simulation over DPI. Other
+//! backends let a driver run without a simulator, e.g. under the
+//! transaction-level model in `tlm.rs`.
+
+use serde::Serialize;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use svdpi::SvScope;
+
+/// Verdict of `Driver::watchdog`, returned to the testbench as is. Anything
+/// but `Continue` ends the run with that result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[repr(u8)]
+pub enum WatchdogStatus {
+    Continue = 0,
+    /// The run completed without a failure.
+    Pass = 1,
+    /// The first failure was data read back wrong, or an access outside the
+    /// mapped memory.
+    FailMismatch = 2,
+    /// The first failure broke the AXI or SDRAM protocol.
+    FailProtocol = 3,
+    /// The timeout came before `+transactions=` read-backs completed.
+    FailTimeout = 4,
+    /// Transactions in flight stopped completing.
+    Hang = 5,
+}
+
+impl fmt::Display for WatchdogStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            WatchdogStatus::Continue => "CONTINUE",
+            WatchdogStatus::Pass => "PASS",
+            WatchdogStatus::FailMismatch => "FAIL_MISMATCH",
+            WatchdogStatus::FailProtocol => "FAIL_PROTOCOL",
+            WatchdogStatus::FailTimeout => "FAIL_TIMEOUT",
+            WatchdogStatus::Hang => "HANG",
+        })
+    }
+}
+
+pub trait SimBackend {
+    /// Current simulation time, in simulator time units.
+    fn time(&self) -> u64;
+
+    /// Start dumping waves to `path`.
+    fn dump_wave(&mut self, path: &str);
+
+    /// The driver has decided to end the run, called from the watchdog.
+    fn finish(&mut self, status: WatchdogStatus);
+}
+
+/// A backend for the simulator that called `cosim_init`. Exported
+/// functions run in the scope captured there.
+pub struct DpiBackend {
+    scope: SvScope,
+}
+
+impl DpiBackend {
+    pub fn new(scope: SvScope) -> Self {
+        Self { scope }
+    }
+}
+
+impl SimBackend for DpiBackend {
+    fn time(&self) -> u64 {
+        svdpi::get_time()
+    }
+
+    #[allow(unused_variables)]
+    fn dump_wave(&mut self, path: &str) {
+        #[cfg(feature = "trace")]
+        crate::dpi::dump_wave(self.scope, path);
+    }
+
+    // the testbench acts on the watchdog return value itself
+    fn finish(&mut self, _status: WatchdogStatus) {}
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    waves: Vec<String>,
+    status: Option<WatchdogStatus>,
+}
+
+/// A backend whose time is advanced by hand. Clones share their state, so
+/// one can be handed to the driver and another kept to observe it.
+#[derive(Debug, Clone, Default)]
+pub struct MockBackend {
+    time: Arc<AtomicU64>,
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockBackend {
+    pub fn advance(&self, units: u64) {
+        self.time.fetch_add(units, Ordering::Relaxed);
+    }
+
+    /// Paths the driver asked to dump waves to.
+    pub fn waves(&self) -> Vec<String> {
+        self.state.lock().unwrap().waves.clone()
+    }
+
+    /// How the run ended, if it has.
+    pub fn status(&self) -> Option<WatchdogStatus> {
+        self.state.lock().unwrap().status
+    }
+}
+
+impl SimBackend for MockBackend {
+    fn time(&self) -> u64 {
+        self.time.load(Ordering::Relaxed)
+    }
+
+    fn dump_wave(&mut self, path: &str) {
+        self.state.lock().unwrap().waves.push(path.to_owned());
+    }
+
+    fn finish(&mut self, status: WatchdogStatus) {
+        self.state.lock().unwrap().status.get_or_insert(status);
+    }
+}
@@ -0,0 +1,64 @@
+//! Translate AXI addresses to the SDRAM location the controller maps them to,
+//! and back.
+//!
+//! ```text
+//! sdram-addr [--part <name>] [--parts <path>] [--cs-width <n>] <address>...
+//! ```
+//!
+//! An address is either an AXI address, decimal or `0x` prefixed, or
+//! `<cs>/<bank>/<row>/<col>[/<byte>]` as printed by the decoder.
+
+use anyhow::{bail, Context, Result};
+use sdramemu::sdram::{AddressMap, Catalogue, SdramAddress, DEFAULT_PART};
+
+fn parse_number(value: &str) -> Result<u64> {
+    sdramemu::parse_number(value).with_context(|| format!("invalid number `{value}`"))
+}
+
+fn main() -> Result<()> {
+    let mut part = DEFAULT_PART.to_owned();
+    let mut catalogue = Catalogue::default();
+    let mut cs_width = 1;
+    let mut addresses = Vec::new();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .with_context(|| format!("`{arg}` needs a value"))
+        };
+        match arg.as_str() {
+            "--part" => part = value()?,
+            "--parts" => catalogue.load(value()?)?,
+            "--cs-width" => cs_width = parse_number(&value()?)? as u32,
+            "-h" | "--help" => {
+                println!(
+                    "usage: sdram-addr [--part <name>] [--parts <path>] [--cs-width <n>] <address>...\n\n\
+                     <address> is an AXI address or <cs>/<bank>/<row>/<col>[/<byte>]"
+                );
+                return Ok(());
+            }
+            _ if arg.starts_with("--") => bail!("unknown option `{arg}`"),
+            _ => addresses.push(arg),
+        }
+    }
+    if cs_width == 0 {
+        bail!("--cs-width must not be zero");
+    }
+
+    let part = catalogue.get(&part)?;
+    let map = AddressMap::new(part.geometry, cs_width);
+    for address in addresses {
+        if address.contains('/') {
+            let sdram: SdramAddress = address.parse().map_err(anyhow::Error::msg)?;
+            let addr = map
+                .encode(&sdram)
+                .with_context(|| format!("`{address}` is outside {}", part.name))?;
+            println!("{sdram:#} -> 0x{addr:08x}");
+        } else {
+            let addr = parse_number(&address)?;
+            println!("0x{addr:08x} -> {:#}", map.decode(addr));
+        }
+    }
+    Ok(())
+}
@@ -1,16 +1,22 @@
 #![allow(non_snake_case)]
 #![allow(unused_variables)]
 
+use crate::axi::{
+    AddressChannel, AxiSignals, ReadDataChannel, WriteDataChannel, WriteResponseChannel,
+};
+use crate::backend::DpiBackend;
 use crate::drive::Driver;
-use crate::{OfflineArgs, AXI_SIZE};
+use crate::profile::{BurstType, Profile, AXI_BOUNDARY};
+use crate::sdram::SdramPins;
+use crate::{fnv1a, OfflineArgs, PAYLOAD_SIZE};
 use common::plusarg::PlusArgMatcher;
-use once_cell::sync::Lazy;
-use rand::prelude::SliceRandom;
+use common::rtl_config::{AxiParameter, RTLConfig};
 use rand::rngs::StdRng;
 use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
 use std::ffi::*;
-use std::sync::{Arc, Mutex};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::ops::Range;
+use std::sync::Mutex;
 use svdpi::SvScope;
 use tracing::{info, trace};
 
@@ -21,7 +27,6 @@ pub type SvBitVecVal = u32;
 // --------------------------
 
 static DPI_TARGET: Mutex<Option<Box<Driver>>> = Mutex::new(None);
-static AWID: Mutex<u8> = Mutex::new(0);
 
 pub trait ToBytes {
     fn to_bytes(&self) -> Vec<u8>;
@@ -55,8 +60,46 @@ impl ToBytesBe for Vec<u32> {
     }
 }
 
+/// Serialize a payload into the bit layout of its Chisel bundle, whose field
+/// widths depend on the AXI parameter of the testbench.
+pub trait ToPayload {
+    fn to_payload(&self, axi: &AxiParameter) -> Vec<u8>;
+}
+
+/// Packs fields LSB first, which is how `asUInt` lays out a Chisel bundle
+/// when its fields are pushed in reverse declaration order.
+#[derive(Default)]
+struct BitPacker {
+    bytes: Vec<u8>,
+    bits: usize,
+}
+
+impl BitPacker {
+    fn push(&mut self, value: u64, width: u32) -> &mut Self {
+        for bit in 0..width {
+            if self.bits % 8 == 0 {
+                self.bytes.push(0);
+            }
+            if bit < u64::BITS && (value >> bit) & 1 != 0 {
+                *self.bytes.last_mut().unwrap() |= 1 << (self.bits % 8);
+            }
+            self.bits += 1;
+        }
+        self
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.bytes)
+    }
+}
+
+/// Payload fields narrower than a byte are widened to 8 bits for a simple C-API.
+fn payload_width(width: u32) -> u32 {
+    width.max(8)
+}
+
 #[derive(Clone, Debug)]
-pub(crate) struct AxiWritePayload {
+pub struct AxiWritePayload {
     pub(crate) id: u8,
     pub(crate) len: u8,
     pub(crate) addr: u32,
@@ -72,88 +115,167 @@ pub(crate) struct AxiWritePayload {
     pub(crate) qos: u8,
     pub(crate) region: u8,
     pub(crate) size: u8,
+    /// Order and tick the driver handed the write to the agent at, not sent
+    /// to it.
+    pub(crate) issue_seq: u64,
+    pub(crate) issue_tick: u64,
 }
 
-static RNG: Lazy<Arc<Mutex<StdRng>>> = Lazy::new(|| {
-    let start = SystemTime::now();
-    let since_epoch = start
-        .duration_since(UNIX_EPOCH)
-        .expect("Clock may have gone backwards");
-    let seed = since_epoch.as_secs();
-    info!("Using seed: {:#x}", seed);
-    Arc::new(Mutex::new(StdRng::seed_from_u64(seed)))
-});
+const ADDR_STREAM: &str = "addr";
+const DATA_STREAM: &str = "data";
+const BURST_STREAM: &str = "burst";
+const STROBE_STREAM: &str = "strobe";
+const ID_STREAM: &str = "id";
+const ATTRIBUTE_STREAM: &str = "attribute";
+pub(crate) const MIX_STREAM: &str = "mix";
+/// Seeds of the random bit flips of each device model.
+pub(crate) const FAULT_STREAM: &str = "fault";
+/// Filler of read payloads that are not issued, kept apart so that idle polls
+/// do not shift the streams of real transactions.
+const IDLE_STREAM: &str = "idle";
+
+/// Random sources of the stimulus, all derived from one seed.
+///
+/// Every field class (address, data, burst shape, strobe, ...) draws from its
+/// own named stream, so drawing more or fewer values for one of them leaves
+/// the sequences of the others untouched.
+pub(crate) struct StimulusRng {
+    seed: u64,
+    streams: HashMap<&'static str, StdRng>,
+    /// AWID of the next write when the profile does not draw one.
+    awid: u8,
+}
 
-impl AxiWritePayload {
-    fn generate_random_strb(use_bit: u8, total_bit: u8, rng: &mut StdRng) -> u8 {
-        let ones_count = 1 << use_bit;
-        let zeros_count = (1 << total_bit) - ones_count;
+impl StimulusRng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            streams: HashMap::new(),
+            awid: 0,
+        }
+    }
 
-        let mut bits: Vec<u8> = vec![1; ones_count as usize];
-        bits.extend(vec![0; zeros_count as usize]);
-        bits.shuffle(rng);
+    pub(crate) fn seed(&self) -> u64 {
+        self.seed
+    }
 
-        let mut result = 0u8;
-        for (i, &bit) in bits.iter().enumerate() {
-            result |= bit << i;
-        }
+    pub(crate) fn stream(&mut self, name: &'static str) -> &mut StdRng {
+        let seed = self.seed;
+        self.streams
+            .entry(name)
+            .or_insert_with(|| StdRng::seed_from_u64(stream_seed(seed, name)))
+    }
 
-        result
+    /// Take the AWID counter, every write advances it.
+    fn next_awid(&mut self) -> u8 {
+        let awid = self.awid;
+        self.awid = awid.wrapping_add(1);
+        awid
     }
-    pub(crate) fn random() -> Self {
-        let mut rng = RNG.lock().unwrap();
-
-        let burst_type = 1;
-        // let burst_type = rng.gen_range(0..=2);
-        let burst_length = match burst_type {
-            0 => rng.gen_range(0..=15),
-            1 => rng.gen_range(0..=u8::MAX),
-            2 => 1 << rng.gen_range(1..=4),
-            _ => 0,
-        };
-        let burst_width = AXI_SIZE >> 3;
-        let MAX_BURST_WIDTH = 7 - burst_width.leading_zeros() as u8;
-        // let burst_size = 0;
-        let burst_size = rng.gen_range(0..=MAX_BURST_WIDTH);
-        let bytes_number = 8 << (1 << burst_size);
-        let total_bit = 1 << MAX_BURST_WIDTH;
-        let used_bit = 1 << burst_size;
-        let strb_sequence = {
-            let mut vec: Vec<u8> = Vec::new();
-            let mut start = (1 << used_bit) - 1;
-            for i in 0..total_bit / used_bit {
-                vec.push(start);
-                start <<= used_bit;
-            }
-            vec
+}
+
+/// Seed of the stream `name`, keyed by the run seed so that old seeds stay
+/// reproducible.
+fn stream_seed(seed: u64, name: &str) -> u64 {
+    fnv1a(seed, name.bytes())
+}
+
+impl AxiWritePayload {
+    pub(crate) fn random(axi: &AxiParameter, profile: &Profile, rng: &mut StimulusRng) -> Self {
+        let burst_type = profile.burst_type(rng.stream(BURST_STREAM));
+        let burst_size = profile.size(rng.stream(BURST_STREAM));
+        let bytes_number = 1u32 << burst_size;
+        let aligned_addr = profile.addr(rng.stream(ADDR_STREAM)) / bytes_number * bytes_number;
+        // WRAP bursts must start aligned
+        let addr = if burst_type != BurstType::Wrap && profile.unaligned(rng.stream(ADDR_STREAM)) {
+            aligned_addr + rng.stream(ADDR_STREAM).gen_range(0..bytes_number)
+        } else {
+            aligned_addr
         };
-        let payload = AxiWritePayload {
-            id: *AWID.lock().unwrap() & 0xF,
-            len: burst_length - 1,
-            addr: rng.gen_range(0xfc000000..=u32::MAX) / bytes_number * bytes_number,
-            data: (0..256).map(|_| rng.gen_range(0..=u32::MAX)).collect(),
-            strb: (0..256)
-                .map(|i| strb_sequence[i % strb_sequence.len()])
+        let mut burst_length = profile.beats(burst_type, rng.stream(BURST_STREAM));
+        if burst_type == BurstType::Incr {
+            // an INCR burst must not cross a 4KB boundary, nor leave its window
+            let boundary = (addr / AXI_BOUNDARY + 1) as u64 * AXI_BOUNDARY as u64;
+            let limit = boundary.min(profile.window_end(addr)) - aligned_addr as u64;
+            burst_length = burst_length.min((limit / bytes_number as u64) as u32);
+        }
+        let len = (burst_length - 1) as u8;
+        let strb = beat_addresses(addr, burst_type as u8, len, burst_size)
+            .into_iter()
+            .map(|beat_addr| {
+                let lanes = beat_lanes(beat_addr, burst_size, axi.bus_bytes());
+                profile.strb(lanes, rng.stream(STROBE_STREAM))
+            })
+            .chain(std::iter::repeat(0))
+            .take(PAYLOAD_SIZE)
+            .collect();
+        let [cache, lock, prot, qos, region] = profile.attributes(rng.stream(ATTRIBUTE_STREAM));
+        let awid = rng.next_awid();
+        let id = profile.id(rng.stream(ID_STREAM)).unwrap_or(awid);
+        let id_mask = ((1u16 << axi.id_width) - 1) as u8;
+        let data_mask = u32::MAX >> (u32::BITS - axi.data_width);
+        AxiWritePayload {
+            id: id & id_mask,
+            len,
+            addr,
+            data: (0..PAYLOAD_SIZE)
+                .map(|_| rng.stream(DATA_STREAM).gen_range(0..=u32::MAX) & data_mask)
+                .collect(),
+            strb,
+            wUser: (0..PAYLOAD_SIZE)
+                .map(|_| rng.stream(DATA_STREAM).gen_range(0..=u8::MAX))
                 .collect(),
-            wUser: (0..256).map(|_| rng.gen_range(0..=u8::MAX)).collect(),
-            awUser: rng.gen_range(0..=u8::MAX),
+            awUser: rng.stream(DATA_STREAM).gen_range(0..=u8::MAX),
             dataValid: 1,
-            burst: burst_type,
-            cache: 0x77,
-            lock: 0x88,
-            prot: 0x99,
-            qos: 0xaa,
-            region: 0xbb,
+            burst: burst_type as u8,
+            cache,
+            lock,
+            prot,
+            qos,
+            region,
             size: burst_size,
-        };
-        *AWID.lock().unwrap() += 1;
-        payload
+            issue_seq: 0,
+            issue_tick: 0,
+        }
+    }
+
+    /// Bytes the burst may touch, as a half-open range.
+    pub(crate) fn footprint(&self) -> Range<u64> {
+        let bytes_number = 1u64 << self.size;
+        let beats = beat_addresses(self.addr, self.burst, self.len, self.size);
+        let first = *beats.iter().min().unwrap() as u64;
+        let last = *beats.iter().max().unwrap() as u64;
+        first..last / bytes_number * bytes_number + bytes_number
+    }
+
+    /// A payload the agent drops, used when no write should be issued.
+    pub(crate) fn idle() -> Self {
+        AxiWritePayload {
+            id: 0,
+            len: 0,
+            addr: 0,
+            data: vec![0; PAYLOAD_SIZE],
+            strb: vec![0; PAYLOAD_SIZE],
+            wUser: vec![0; PAYLOAD_SIZE],
+            awUser: 0,
+            dataValid: 0,
+            burst: 0,
+            cache: 0,
+            lock: 0,
+            prot: 0,
+            qos: 0,
+            region: 0,
+            size: 0,
+            issue_seq: 0,
+            issue_tick: 0,
+        }
     }
 }
 
-impl ToBytes for AxiWritePayload {
-    fn to_bytes(&self) -> Vec<u8> {
-        vec![
+impl ToPayload for AxiWritePayload {
+    fn to_payload(&self, axi: &AxiParameter) -> Vec<u8> {
+        let mut packer = BitPacker::default();
+        for field in [
             self.size,
             self.region,
             self.qos,
@@ -162,20 +284,59 @@ impl ToBytes for AxiWritePayload {
             self.cache,
             self.burst,
             self.dataValid,
-            self.awUser,
-        ]
-        .into_iter()
-        .chain(self.wUser.clone())
-        .chain(self.strb.clone())
-        .chain(self.data.to_bytes())
-        .chain(self.addr.to_bytes())
-        .chain(vec![self.len, self.id])
-        .collect::<Vec<u8>>()
+        ] {
+            packer.push(field as u64, 8);
+        }
+        packer.push(self.awUser as u64, payload_width(axi.aw_user_width()));
+        for &user in &self.wUser {
+            packer.push(user as u64, payload_width(axi.w_user_width()));
+        }
+        for &strb in &self.strb {
+            packer.push(strb as u64, payload_width(axi.bus_bytes()));
+        }
+        for &data in &self.data {
+            packer.push(data as u64, axi.data_width);
+        }
+        packer
+            .push(self.addr as u64, axi.addr_width)
+            .push(self.len as u64, 8)
+            .push(self.id as u64, payload_width(axi.id_width))
+            .finish()
     }
 }
 
+/// Start address of every beat of a burst, see AXI4 spec A3.4.1.
+pub(crate) fn beat_addresses(addr: u32, burst: u8, len: u8, size: u8) -> Vec<u32> {
+    let bytes_number = 1u32 << size;
+    let transfer_count = len as u32 + 1;
+    let aligned_addr = addr / bytes_number * bytes_number;
+    let wrap_size = bytes_number * transfer_count;
+    let lower_boundary = addr / wrap_size * wrap_size;
+    (0..transfer_count)
+        .map(|beat| match burst {
+            // FIXED
+            0 => addr,
+            // INCR, only the first beat may be unaligned
+            1 if beat == 0 => addr,
+            1 => aligned_addr + beat * bytes_number,
+            // WRAP
+            2 => lower_boundary + (addr - lower_boundary + beat * bytes_number) % wrap_size,
+            _ => panic!("unknown burst type: {:?}", burst),
+        })
+        .collect()
+}
+
+/// Byte lanes a beat at `addr` occupies on a `bus_bytes` wide bus, see AXI4
+/// spec A3.4.3. Lanes below an unaligned start address are inactive.
+pub(crate) fn beat_lanes(addr: u32, size: u8, bus_bytes: u32) -> u8 {
+    let bytes_number = 1u32 << size;
+    let lane_low = addr % bus_bytes;
+    let lane_high = (addr / bytes_number * bytes_number) % bus_bytes + bytes_number - 1;
+    (((1u16 << (lane_high + 1)) - (1u16 << lane_low)) & 0xff) as u8
+}
+
 #[derive(Clone, Debug)]
-pub(crate) struct AxiReadPayload {
+pub struct AxiReadPayload {
     pub(crate) addr: u32,
     pub(crate) id: u8,
     pub(crate) user: u8,
@@ -191,8 +352,8 @@ pub(crate) struct AxiReadPayload {
 }
 
 impl AxiReadPayload {
-    pub(crate) fn random() -> Self {
-        let mut rng = rand::thread_rng();
+    pub(crate) fn random(rng: &mut StimulusRng) -> Self {
+        let rng = rng.stream(IDLE_STREAM);
         AxiReadPayload {
             addr: rng.gen_range(0..=255),
             id: rng.gen_range(0..=255),
@@ -226,9 +387,10 @@ impl AxiReadPayload {
     }
 }
 
-impl ToBytes for AxiReadPayload {
-    fn to_bytes(&self) -> Vec<u8> {
-        vec![
+impl ToPayload for AxiReadPayload {
+    fn to_payload(&self, axi: &AxiParameter) -> Vec<u8> {
+        let mut packer = BitPacker::default();
+        for field in [
             self.valid,
             self.size,
             self.region,
@@ -238,12 +400,14 @@ impl ToBytes for AxiReadPayload {
             self.len,
             self.cache,
             self.burst,
-            self.user,
-            self.id,
-        ]
-        .into_iter()
-        .chain(self.addr.to_bytes())
-        .collect()
+        ] {
+            packer.push(field as u64, 8);
+        }
+        packer
+            .push(self.user as u64, payload_width(axi.ar_user_width()))
+            .push(self.id as u64, payload_width(axi.id_width))
+            .push(self.addr as u64, axi.addr_width)
+            .finish()
     }
 }
 
@@ -251,8 +415,8 @@ unsafe fn write_to_pointer(dst: *mut u8, data: &[u8]) {
     std::ptr::copy_nonoverlapping(data.as_ptr(), dst, data.len());
 }
 
-unsafe fn fill_axi_payload<T: ToBytes>(dst: *mut SvBitVecVal, payload: &T) {
-    let data = payload.to_bytes();
+unsafe fn fill_axi_payload<T: ToPayload>(dst: *mut SvBitVecVal, payload: &T, axi: &AxiParameter) {
+    let data = payload.to_payload(axi);
     // info!("data length: {:?}", data.len());
     write_to_pointer(dst as *mut u8, &data);
 }
@@ -271,7 +435,7 @@ unsafe extern "C" fn axi_read_done_axi4Probe(
     rresp: u8,
     ruser: u8,
 ) {
-    let rdata_slice = std::slice::from_raw_parts(rdata, 256);
+    let rdata_slice = std::slice::from_raw_parts(rdata, PAYLOAD_SIZE);
     let mut driver = DPI_TARGET.lock().unwrap();
     let driver = driver.as_mut().unwrap();
     driver.axi_read_done(rdata_slice.to_vec(), len, last_data, rid, rresp, ruser);
@@ -284,7 +448,7 @@ unsafe extern "C" fn axi_write_ready_axi4Probe(payload: *mut SvBitVecVal) {
     let mut driver = DPI_TARGET.lock().unwrap();
     let driver = driver.as_mut().unwrap();
     let response = driver.axi_write_ready();
-    fill_axi_payload(payload, &response);
+    fill_axi_payload(payload, &response, driver.config.axi());
 }
 
 /// evaluate at B fire.
@@ -303,7 +467,122 @@ unsafe extern "C" fn axi_read_ready_axi4Probe(payload: *mut SvBitVecVal) {
     let mut driver = DPI_TARGET.lock().unwrap();
     let driver = driver.as_mut().unwrap();
     let response = driver.axi_read_ready();
-    fill_axi_payload(payload, &response);
+    fill_axi_payload(payload, &response, driver.config.axi());
+}
+
+/// evaluate at every rising edge of the SDRAM clock of chip select `cs`.
+#[no_mangle]
+unsafe extern "C" fn sdram_tick(
+    cs: c_uchar,
+    addr: u32,
+    bs: c_uchar,
+    cke: c_uchar,
+    cs_n: c_uchar,
+    ras_n: c_uchar,
+    cas_n: c_uchar,
+    we_n: c_uchar,
+    dqm: c_uchar,
+    dq_i: u32,
+    dq_o: *mut u32,
+) {
+    let pins = SdramPins {
+        addr: addr as u16,
+        bs,
+        cke: cke != 0,
+        cs_n: cs_n != 0,
+        ras_n: ras_n != 0,
+        cas_n: cas_n != 0,
+        we_n: we_n != 0,
+        dqm,
+        dq: dq_i,
+    };
+    let mut driver = DPI_TARGET.lock().unwrap();
+    if let Some(driver) = driver.as_mut() {
+        *dq_o = driver.sdram_tick(cs as usize, &pins);
+    }
+}
+
+/// evaluate at every rising edge of the AXI clock, with the signals of every
+/// channel of the controller's slave port.
+#[no_mangle]
+unsafe extern "C" fn axi_monitor_tick(
+    reset: c_uchar,
+    aw_valid: c_uchar,
+    aw_ready: c_uchar,
+    aw_id: c_uchar,
+    aw_addr: u32,
+    aw_len: c_uchar,
+    aw_size: c_uchar,
+    aw_burst: c_uchar,
+    w_valid: c_uchar,
+    w_ready: c_uchar,
+    w_data: u32,
+    w_strb: c_uchar,
+    w_last: c_uchar,
+    b_valid: c_uchar,
+    b_ready: c_uchar,
+    b_id: c_uchar,
+    b_resp: c_uchar,
+    ar_valid: c_uchar,
+    ar_ready: c_uchar,
+    ar_id: c_uchar,
+    ar_addr: u32,
+    ar_len: c_uchar,
+    ar_size: c_uchar,
+    ar_burst: c_uchar,
+    r_valid: c_uchar,
+    r_ready: c_uchar,
+    r_id: c_uchar,
+    r_data: u32,
+    r_resp: c_uchar,
+    r_last: c_uchar,
+) {
+    let signals = AxiSignals {
+        reset: reset != 0,
+        aw: AddressChannel {
+            valid: aw_valid != 0,
+            ready: aw_ready != 0,
+            id: aw_id,
+            addr: aw_addr,
+            len: aw_len,
+            size: aw_size,
+            burst: aw_burst,
+        },
+        w: WriteDataChannel {
+            valid: w_valid != 0,
+            ready: w_ready != 0,
+            data: w_data,
+            strb: w_strb,
+            last: w_last != 0,
+        },
+        b: WriteResponseChannel {
+            valid: b_valid != 0,
+            ready: b_ready != 0,
+            id: b_id,
+            resp: b_resp,
+        },
+        ar: AddressChannel {
+            valid: ar_valid != 0,
+            ready: ar_ready != 0,
+            id: ar_id,
+            addr: ar_addr,
+            len: ar_len,
+            size: ar_size,
+            burst: ar_burst,
+        },
+        r: ReadDataChannel {
+            valid: r_valid != 0,
+            ready: r_ready != 0,
+            id: r_id,
+            data: r_data,
+            resp: r_resp,
+            last: r_last != 0,
+        },
+    };
+    let mut driver = DPI_TARGET.lock().unwrap();
+    if let Some(driver) = driver.as_mut() {
+        driver.axi_monitor_tick(&signals);
+    }
 }
 
 #[no_mangle]
@@ -322,9 +601,14 @@ unsafe extern "C" fn cosim_init() {
     let args = OfflineArgs::from_plusargs(&plusargs);
     args.common_args.setup_logger().unwrap();
 
+    let config =
+        RTLConfig::from_file(&args.common_args.rtl_config).expect("failed to load rtl config");
+    info!("rtl config: {:?}", config);
+
     let scope = SvScope::get_current().expect("failed to get scope in cosim_init");
 
-    let driver = Box::new(Driver::new(scope, &args));
+    let backend = Box::new(DpiBackend::new(scope));
+    let driver = Box::new(Driver::new(backend, &args, config));
     let mut dpi_target = DPI_TARGET.lock().unwrap();
     assert!(
         dpi_target.is_none(),
@@ -361,3 +645,37 @@ pub(crate) fn dump_wave(scope: svdpi::SvScope, path: &str) {
         dpi_export::dump_wave(path_cstring.as_ptr());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shadow_mem::MemRegion;
+
+    const RTL_CONFIG: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../configs/SDRAMControllerTestBenchMain.json"
+    );
+
+    #[test]
+    fn numbers_writes_per_stimulus() {
+        let config = RTLConfig::from_file(RTL_CONFIG).unwrap();
+        let axi = config.axi();
+        let mut profile = Profile::default();
+        profile
+            .resolve(
+                axi.max_size(),
+                &[MemRegion {
+                    base: 0,
+                    size: 0x1000,
+                }],
+            )
+            .unwrap();
+        // two stimuli drawn in lockstep, as parallel drivers would
+        let mut rngs = [StimulusRng::new(1), StimulusRng::new(1)];
+        for awid in 0..20 {
+            for rng in &mut rngs {
+                assert_eq!(AxiWritePayload::random(axi, &profile, rng).id, awid & 0xf);
+            }
+        }
+    }
+}
@@ -1,178 +1,32 @@
-use svdpi::{get_time, SvScope};
 use tracing::{error, info, trace};
 
+use crate::axi::{AxiSignals, ProtocolMonitor};
+use crate::backend::{SimBackend, WatchdogStatus};
 use crate::dpi::*;
-use crate::driver_assert_eq;
-use crate::{OfflineArgs, AXI_SIZE};
+use crate::failure::{FailureKind, Failures, DEFAULT_MAX_ERRORS};
+use crate::profile::Profile;
+use crate::report::{BeatMismatch, ByteMismatch, MismatchReport, DEFAULT_REPORT_PATH};
+use crate::sdram::{
+    AddressMap, Catalogue, Command, Coverage, Fault, FaultModel, InitChecker, ModeChecker,
+    ModeRegister, RefreshMonitor, SdramAddress, SdramDevice, SdramPins, TimingChecker, WriteBeat,
+    DEFAULT_PART,
+};
+use crate::shadow_mem::{MemRegion, Provenance, ShadowMem, WriteRecord};
+use crate::summary::{config_hash, RunSummary, DEFAULT_SUMMARY_PATH};
+use crate::tlm::AxiMaster;
+use crate::{driver_assert_eq, driver_error};
+use crate::{OfflineArgs, AGENT_OUTSTANDING, PAYLOAD_SIZE, SDRAM_MHZ};
+use common::rtl_config::RTLConfig;
+use rand::Rng;
 use std::collections::{HashMap, VecDeque};
-
-struct ShadowMem {
-    mem: Vec<u8>,
-}
-
-impl ShadowMem {
-    pub fn new() -> Self {
-        Self {
-            mem: vec![0; 0xffff_ffff - 0xfc00_0000],
-        }
-    }
-
-    fn is_addr_align(&self, addr: u32, size: u8) -> bool {
-        let bytes_number = 1 << size;
-        let aligned_addr = addr / bytes_number * bytes_number;
-        addr == aligned_addr
-    }
-
-    // size: 1 << arsize
-    // return: Vec<u8> with len=bus_size
-    pub fn read_mem_axi(&self, payload: AxiReadPayload) -> Vec<u8> {
-        let bytes_number: u32 = 1 << payload.size;
-        let transfer_count: u32 = (payload.len + 1) as u32;
-
-        let mut lower_boundary = 0;
-        let mut upper_boundary = 0;
-
-        let mut data: Vec<u8> = vec![];
-
-        if payload.burst == 2 {
-            lower_boundary =
-                payload.addr / (bytes_number * transfer_count) * (bytes_number * transfer_count);
-            upper_boundary = lower_boundary + bytes_number * transfer_count;
-            assert!(
-                transfer_count == 2
-                    || transfer_count == 4
-                    || transfer_count == 8
-                    || transfer_count == 16,
-                "unsupported burst len"
-            );
-        }
-
-        let mut current_addr = payload.addr - 0xfc000000;
-        assert!(
-            self.is_addr_align(payload.addr, payload.size),
-            "address is unaligned!"
-        );
-
-        for _ in 0..transfer_count {
-            data.extend_from_slice(
-                &self.mem[current_addr as usize..(current_addr + bytes_number) as usize],
-            );
-
-            current_addr = match payload.burst {
-                0 => current_addr,                // FIXED
-                1 => current_addr + bytes_number, // INCR
-                2 => {
-                    if current_addr + bytes_number >= upper_boundary {
-                        lower_boundary
-                    } else {
-                        current_addr + bytes_number
-                    }
-                } // WRAP
-                _ => {
-                    panic!("unknown burst type: {:?}", payload.burst);
-                }
-            }
-        }
-        data
-    }
-
-    // size: 1 << awsize
-    // bus_size: AXI bus width in bytes
-    // masks: write strokes, len=bus_size
-    // data: write data, len=bus_size
-    pub fn write_mem_axi(&mut self, payload: AxiWritePayload) {
-        let transfer_count = (payload.len + 1) as usize;
-
-        // assert!(
-        //     transfer_count == payload.data.len()
-        //         && transfer_count == payload.strb.len(),
-        //     "malformed payload: transfer_count = {:?}, payload.data.len = {:?}, payload.strb.len = {:?}",
-        //     transfer_count, payload.data.len(), payload.strb.len(),
-        // );
-
-        let bytes_number: u32 = 1 << payload.size;
-
-        let mut lower_boundary = 0;
-        let mut upper_boundary = 0;
-        if payload.burst == 2 {
-            lower_boundary = payload.addr / (bytes_number * transfer_count as u32)
-                * (bytes_number * transfer_count as u32);
-            upper_boundary = lower_boundary + bytes_number * transfer_count as u32;
-            assert!(
-                transfer_count == 2
-                    || transfer_count == 4
-                    || transfer_count == 8
-                    || transfer_count == 16,
-                "unsupported burst len: {}",
-                payload.len
-            );
-        }
-
-        let mut current_addr = payload.addr - 0xfc000000;
-        assert!(
-            self.is_addr_align(payload.addr, payload.size),
-            "address is unaligned!"
-        );
-
-        for item_idx in 0..transfer_count {
-            if payload.strb[item_idx] == 0 {
-                continue;
-            }
-
-            assert_eq!(
-                payload.strb[item_idx].count_ones(),
-                1 << payload.size,
-                "the number of will write bytes is not equal, size = {}",
-                payload.size
-            );
-
-            info!(
-                "writing(0x{:02x}) 0x{:08x} -> 0x{:08x}/{} with strb:0b{:04b}",
-                payload.id,
-                payload.data[item_idx],
-                current_addr,
-                match payload.burst {
-                    0 => "FIX",
-                    1 => "INCR",
-                    2 => "WARP",
-                    _ => "UNKNOWN",
-                },
-                payload.strb[item_idx]
-            );
-
-            let mut write_count = 0;
-            for byte_idx in 0..AXI_SIZE / 8 {
-                let byte_mask: bool =
-                    (payload.strb[item_idx] >> (AXI_SIZE / 8 - (byte_idx + 1))) & 1 != 0;
-                if byte_mask {
-                    self.mem[(current_addr + write_count as u32) as usize] =
-                        (payload.data[item_idx] >> ((AXI_SIZE / 8 - (byte_idx + 1)) * 8) & 0xff)
-                            as u8;
-                    write_count += 1;
-                }
-            }
-
-            current_addr = match payload.burst {
-                0 => current_addr,                // FIXED
-                1 => current_addr + bytes_number, // INCR
-                2 => {
-                    if current_addr + bytes_number >= upper_boundary {
-                        lower_boundary
-                    } else {
-                        current_addr + bytes_number
-                    }
-                } // WRAP
-                _ => {
-                    panic!("unknown burst type: {:?}", payload.burst);
-                }
-            }
-        }
-    }
-}
+use std::ops::Range;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub(crate) struct Driver {
-    // SvScope from cosim_init
-    scope: SvScope,
+    backend: Box<dyn SimBackend + Send>,
+
+    pub(crate) config: RTLConfig,
 
     #[cfg(feature = "trace")]
     wave_path: String,
@@ -184,18 +38,142 @@ pub(crate) struct Driver {
     dump_started: bool,
     dump_manual_finish: bool,
     timeout: u64,
+    failures: Failures,
+    /// Read-backs the run passes after, if any.
+    transactions: Option<u64>,
+    /// Ticks without a completion, while transactions are in flight, the run
+    /// is deemed hung after, 0 to never.
+    hang_ticks: u64,
+    /// Tick a transaction last completed, or nothing was in flight, at.
+    last_progress: u64,
+    /// JSON file the run summary is written to.
+    run_summary: PathBuf,
+    config_hash: String,
 
     clock_flip_time: u64,
 
     shadow_mem: ShadowMem,
+    /// JSON lines file of the mismatch reports.
+    mismatch_report: PathBuf,
+    address_map: AddressMap,
+    chip_selects: Vec<ChipSelect>,
+    axi_monitor: ProtocolMonitor,
 
+    /// Limit of writes and of reads in flight.
+    outstanding: usize,
+    /// Completed writes waiting for their read-back.
     axi_write_done_fifo: VecDeque<AxiWritePayload>,
-    axi_write_fifo: VecDeque<AxiWritePayload>,
-    axi_read_fifo: VecDeque<AxiWritePayload>,
+    /// A generated write held back until it no longer overlaps a transaction
+    /// in flight.
+    pending_write: Option<AxiWritePayload>,
+    outstanding_writes: OutstandingTable,
+    /// Reads in flight, along with the write they read back.
+    outstanding_reads: OutstandingTable,
+
+    rng: StimulusRng,
+    profile: Profile,
+    /// Whether the next transaction should be a read-back, only tracked when
+    /// the profile sets a read/write mix.
+    next_is_read: Option<bool>,
 
     statistic: HashMap<String, u64>,
 }
 
+/// Default of `+hang-ticks=`, long enough for the controller's power-up
+/// sequence.
+const DEFAULT_HANG_TICKS: u64 = 50_000;
+
+/// Divergences the end-of-test sweep lists, the rest are only counted.
+const SWEEP_REPORT_LIMIT: usize = 32;
+
+/// What is attached to the pins of one chip select.
+struct ChipSelect {
+    /// The device model, `None` when the testbench uses the vendor model.
+    device: Option<SdramDevice>,
+    init: InitChecker,
+    mode: ModeChecker,
+    timing: TimingChecker,
+    refresh: RefreshMonitor,
+    coverage: Coverage,
+}
+
+/// Transactions waiting for their response. AXI only orders responses within
+/// an ID, so they are matched against the oldest transaction of their ID.
+#[derive(Default)]
+struct OutstandingTable {
+    by_id: HashMap<u8, VecDeque<AxiWritePayload>>,
+    count: usize,
+}
+
+impl OutstandingTable {
+    fn push(&mut self, payload: AxiWritePayload) {
+        self.by_id.entry(payload.id).or_default().push_back(payload);
+        self.count += 1;
+    }
+
+    fn pop(&mut self, id: u8) -> Option<AxiWritePayload> {
+        let payload = self.by_id.get_mut(&id)?.pop_front()?;
+        self.count -= 1;
+        Some(payload)
+    }
+
+    fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Whether a transaction in flight may touch a byte of `footprint`.
+    fn overlaps(&self, footprint: &Range<u64>) -> bool {
+        self.by_id.values().flatten().any(|payload| {
+            let other = payload.footprint();
+            other.start < footprint.end && footprint.start < other.end
+        })
+    }
+
+    /// Whether the write in flight with a beat on the byte at `addr`, modulo
+    /// `capacity` as the address map decodes it, strobes it. `None` if there
+    /// is no such write, writes in flight never overlap.
+    fn strobed(&self, addr: u64, bus_bytes: u32, capacity: u64) -> Option<bool> {
+        self.by_id.values().flatten().find_map(|payload| {
+            let beats = beat_addresses(payload.addr, payload.burst, payload.len, payload.size);
+            beats
+                .into_iter()
+                .enumerate()
+                .filter_map(|(beat, beat_addr)| {
+                    let bus_addr = (beat_addr / bus_bytes * bus_bytes) as u64;
+                    let lane = (addr + capacity - bus_addr % capacity) % capacity;
+                    let active = beat_lanes(beat_addr, payload.size, bus_bytes);
+                    (lane < bus_bytes as u64 && (active >> lane) & 1 != 0)
+                        .then(|| (payload.strb[beat] >> lane) & 1 != 0)
+                })
+                .reduce(|strobed, other| strobed || other)
+        })
+    }
+}
+
+/// Pick a runtime knob from its plusarg, then the rtl config, then the value
+/// baked in at build time. The choice is logged so a run can be reproduced
+/// from its log alone.
+fn resolve_knob(
+    name: &str,
+    plusarg: Option<u64>,
+    config: Option<u64>,
+    build_time: Option<&str>,
+) -> Option<u64> {
+    let build_time = build_time.map(|value| {
+        value
+            .parse::<u64>()
+            .unwrap_or_else(|_| panic!("invalid build-time {name}: `{value}`"))
+    });
+    let (value, source) = match (plusarg, config, build_time) {
+        (Some(value), _, _) => (value, "plusarg"),
+        (None, Some(value), _) => (value, "rtl config"),
+        (None, None, Some(value)) => (value, "build time"),
+        (None, None, None) => return None,
+    };
+    info!("{name} = {value} (from {source})");
+    Some(value)
+}
+
 #[cfg(feature = "trace")]
 fn parse_range(input: &str) -> (u64, u64) {
     if input.is_empty() {
@@ -230,14 +208,174 @@ fn parse_range(input: &str) -> (u64, u64) {
 
 impl Driver {
     fn get_tick(&self) -> u64 {
-        get_time() / self.clock_flip_time
+        self.backend.time() / self.clock_flip_time
     }
 
-    pub(crate) fn new(scope: SvScope, args: &OfflineArgs) -> Self {
+    pub(crate) fn new(
+        backend: Box<dyn SimBackend + Send>,
+        args: &OfflineArgs,
+        config: RTLConfig,
+    ) -> Self {
         #[cfg(feature = "trace")]
         let (dump_start, dump_end) = parse_range(&args.dump_range);
+
+        let timescale =
+            resolve_knob("timescale", args.timescale, None, option_env!("TIMESCALE")).unwrap_or(1);
+        let clock_flip_time = resolve_knob(
+            "clock flip time",
+            args.clock_flip_time,
+            Some(config.test_verbatim_parameter.clock_flip_tick * timescale),
+            option_env!("CLOCK_FLIP_TIME"),
+        )
+        .unwrap();
+        assert!(clock_flip_time > 0, "clock flip time must not be zero");
+        let timeout = resolve_knob(
+            "timeout",
+            args.timeout,
+            Some(config.timeout),
+            option_env!("TIMEOUT"),
+        )
+        .unwrap_or(u64::MAX);
+        let outstanding = args
+            .outstanding
+            .map_or(AGENT_OUTSTANDING, |outstanding| outstanding as usize);
+        assert!(outstanding > 0, "outstanding must not be zero");
+        let seed = args.seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Clock may have gone backwards")
+                .as_secs()
+        });
+        info!(
+            "Using seed: {:#x}, pass `+seed={:#x}` to reproduce",
+            seed, seed
+        );
+        let mut rng = StimulusRng::new(seed);
+
+        let mut catalogue = Catalogue::default();
+        if let Some(path) = &args.sdram_parts {
+            catalogue.load(path).expect("failed to load sdram parts");
+        }
+        let part = catalogue
+            .get(args.sdram_part.as_deref().unwrap_or(DEFAULT_PART))
+            .expect("invalid `+sdram-part=`");
+        info!("Using sdram part: {part:?}");
+        part.check_controller(config.sdram())
+            .expect("invalid `+sdram-part=`");
+        let (geometry, timing) = (part.geometry, part.timing);
+        let address_map = AddressMap::new(geometry, config.sdram().cs_width);
+        info!(
+            "Address map: {} chip select(s) of {:#x} bytes, repeating every {:#x}",
+            address_map.cs_width(),
+            address_map.cs_bytes(),
+            address_map.capacity()
+        );
+
+        let regions = match &args.mem_regions {
+            Some(regions) => MemRegion::parse_list(regions).expect("invalid `+mem-regions=`"),
+            None => Vec::new(),
+        };
+        let provenance = match &args.provenance {
+            Some(provenance) => provenance.parse().expect("invalid `+provenance=`"),
+            None => Provenance::Byte,
+        };
+        info!("Keeping {provenance:?} provenance of the shadow memory");
+        let shadow_mem = ShadowMem::new(&config, regions, address_map, provenance)
+            .expect("invalid memory regions");
+        for region in shadow_mem.regions() {
+            let chip_selects: Vec<String> = address_map
+                .cs_ranges(region.base..region.end())
+                .into_iter()
+                .map(|(cs, range)| format!("cs{cs} {:#x}..{:#x}", range.start, range.end))
+                .collect();
+            info!(
+                "Mapped memory region: {region}, {}",
+                chip_selects.join(", ")
+            );
+        }
+        let mismatch_report = PathBuf::from(
+            args.mismatch_report
+                .as_deref()
+                .unwrap_or(DEFAULT_REPORT_PATH),
+        );
+        info!("Mismatch reports go to {}", mismatch_report.display());
+        let run_summary =
+            PathBuf::from(args.run_summary.as_deref().unwrap_or(DEFAULT_SUMMARY_PATH));
+        info!("Run summary goes to {}", run_summary.display());
+        let hang_ticks = args.hang_ticks.unwrap_or(DEFAULT_HANG_TICKS);
+        if let Some(transactions) = args.transactions {
+            info!("Passing after {transactions} read-backs");
+        }
+
+        let mut profile = match &args.profile {
+            Some(path) => Profile::from_file(path).expect("failed to load profile"),
+            None => Profile::default(),
+        };
+        profile
+            .resolve(config.axi().max_size(), shadow_mem.regions())
+            .expect("invalid profile");
+        info!(
+            "Using profile: {}",
+            args.profile.as_deref().unwrap_or("default")
+        );
+        let next_is_read = profile.read_next(rng.stream(MIX_STREAM));
+
+        if config.use_device_model {
+            info!(
+                "Using the device model for {} chip select(s)",
+                config.sdram().cs_width
+            );
+        }
+        let sdram_mhz = args.sdram_mhz.unwrap_or(SDRAM_MHZ);
+        assert!(sdram_mhz > 0, "sdram clock must not be zero");
+        info!("Checking SDRAM timing at {sdram_mhz} MHz");
+        // the controller moves a whole AXI beat per READ or WRITE
+        let transfer_beats = config.axi().data_width / config.sdram().data_width;
+
+        let mut faults = Vec::new();
+        if let Some(path) = &args.sdram_faults {
+            faults.extend(Fault::load(path).expect("failed to load sdram faults"));
+        }
+        if let Some(list) = &args.sdram_fault {
+            faults.extend(Fault::parse_list(list).expect("invalid `+sdram-fault=`"));
+        }
+        assert!(
+            faults.is_empty() || config.use_device_model,
+            "sdram faults are injected into the device model, enable `useDeviceModel`"
+        );
+        for fault in &faults {
+            assert!(
+                fault.cs() < config.sdram().cs_width,
+                "sdram fault `{fault}` is on a chip select the rtl config does not have"
+            );
+            info!("Injecting sdram fault: {fault}");
+        }
+
+        let chip_selects = (0..config.sdram().cs_width)
+            .map(|cs| ChipSelect {
+                device: config.use_device_model.then(|| {
+                    let mut device = SdramDevice::new(geometry);
+                    let faults: Vec<&Fault> =
+                        faults.iter().filter(|fault| fault.cs() == cs).collect();
+                    if !faults.is_empty() {
+                        let seed = rng.stream(FAULT_STREAM).gen();
+                        device.inject(
+                            FaultModel::new(geometry, faults, sdram_mhz, seed)
+                                .expect("invalid sdram fault"),
+                        );
+                    }
+                    device
+                }),
+                init: InitChecker::new(&timing, sdram_mhz),
+                mode: ModeChecker::new(geometry, transfer_beats),
+                timing: TimingChecker::new(geometry, &timing, sdram_mhz),
+                refresh: RefreshMonitor::new(geometry, &timing, sdram_mhz),
+                coverage: Coverage::new(geometry),
+            })
+            .collect();
+
         Self {
-            scope,
+            backend,
 
             #[cfg(feature = "trace")]
             wave_path: args.wave_path.to_owned(),
@@ -248,14 +386,30 @@ impl Driver {
             #[cfg(feature = "trace")]
             dump_started: false,
             dump_manual_finish: false,
-            timeout: std::env::var("TIMEOUT")
-                .map(|s| s.parse::<u64>().unwrap_or(u64::MAX))
-                .unwrap_or(u64::MAX),
-            clock_flip_time: env!("CLOCK_FLIP_TIME").parse().unwrap(),
-            shadow_mem: ShadowMem::new(),
-            axi_read_fifo: VecDeque::new(),
+            timeout,
+            failures: Failures::new(args.max_errors.unwrap_or(DEFAULT_MAX_ERRORS)),
+            transactions: args.transactions,
+            hang_ticks,
+            last_progress: 0,
+            run_summary,
+            config_hash: config_hash(
+                &std::fs::read(&args.common_args.rtl_config).expect("failed to read rtl config"),
+            ),
+            clock_flip_time,
+            shadow_mem,
+            mismatch_report,
+            address_map,
+            chip_selects,
+            axi_monitor: ProtocolMonitor::new(config.axi().bus_bytes()),
+            outstanding,
             axi_write_done_fifo: VecDeque::new(),
-            axi_write_fifo: VecDeque::new(),
+            pending_write: None,
+            outstanding_writes: OutstandingTable::default(),
+            outstanding_reads: OutstandingTable::default(),
+            rng,
+            profile,
+            next_is_read,
+            config,
             statistic: HashMap::from([
                 ("axi_write".to_string(), 0),
                 ("axi_write_done".to_string(), 0),
@@ -273,24 +427,32 @@ impl Driver {
         }
     }
 
-    pub(crate) fn watchdog(&mut self) -> u8 {
-        const WATCHDOG_CONTINUE: u8 = 0;
-        const WATCHDOG_TIMEOUT: u8 = 1;
-        const WATCHDOG_FINISH: u8 = 2;
-
+    pub(crate) fn watchdog(&mut self) -> WatchdogStatus {
         let tick = self.get_tick();
 
-        let mut ret = WATCHDOG_CONTINUE;
+        let mut finish = false;
+        let mut timeout = false;
+        let mut hang = false;
 
         if self.dump_manual_finish {
-            info!("[{tick}] manual finish, exiting");
-            ret = WATCHDOG_FINISH;
+            info!(
+                "[{tick}] {} failure(s) reached, exiting",
+                self.failures.total()
+            );
+            finish = true;
+        }
+
+        if let Some(transactions) = self.transactions {
+            if self.statistic["axi_read_done"] >= transactions {
+                info!("[{tick}] {transactions} read-backs done, exiting");
+                finish = true;
+            }
         }
 
         #[cfg(feature = "trace")]
         if self.dump_end != 0 && tick > self.dump_end {
             info!("[{tick}] run to dump end, exiting");
-            ret = WATCHDOG_TIMEOUT;
+            timeout = true;
         }
 
         #[cfg(feature = "trace")]
@@ -299,63 +461,298 @@ impl Driver {
             self.dump_started = true;
         }
 
+        let in_flight = self.outstanding_writes.len() + self.outstanding_reads.len();
+        if in_flight == 0 {
+            self.last_progress = tick;
+        } else if self.hang_ticks != 0 && tick - self.last_progress >= self.hang_ticks {
+            info!(
+                "[{tick}] {in_flight} transaction(s) in flight and none completed since tick {}, exiting",
+                self.last_progress
+            );
+            hang = true;
+        }
+
         if tick >= self.timeout {
             info!("[{tick}] timeout triggered, exiting");
-            ret = WATCHDOG_TIMEOUT;
+            timeout = true;
         }
 
-        trace!("[{tick}] watchdog continue");
-
-        if ret != WATCHDOG_CONTINUE {
-            info!("statistic:\naxi_write: \n\tdone: {}\n\ttotal: {}\naxi_read:\n\tdone: {}\n\ttotal: {}\n",
-        self.statistic["axi_write_done"], self.statistic["axi_write"], self.statistic["axi_read_done"], self.statistic["axi_read"])
+        if !(finish || timeout || hang) {
+            trace!("[{tick}] watchdog continue");
+            return WatchdogStatus::Continue;
         }
 
+        info!("statistic:\nseed: {:#x}\naxi_write: \n\tdone: {}\n\ttotal: {}\naxi_read:\n\tdone: {}\n\ttotal: {}\n",
+        self.rng.seed(), self.statistic["axi_write_done"], self.statistic["axi_write"], self.statistic["axi_read_done"], self.statistic["axi_read"]);
+        self.chip_select_statistics();
+        self.sweep();
+        info!("{}", self.failures);
+
+        // the first failure is the likeliest cause of the others, and of a hang
+        let ret = match self.failures.first() {
+            Some((_, kind)) => kind.status(),
+            None if hang => WatchdogStatus::Hang,
+            None if timeout
+                && self
+                    .transactions
+                    .is_some_and(|transactions| self.statistic["axi_read_done"] < transactions) =>
+            {
+                WatchdogStatus::FailTimeout
+            }
+            None => WatchdogStatus::Pass,
+        };
+        info!("[{tick}] result: {ret}");
+        self.write_summary(tick, ret);
+        self.backend.finish(ret);
+
         ret
     }
 
+    fn write_summary(&self, tick: u64, result: WatchdogStatus) {
+        let summary = RunSummary {
+            result,
+            seed: format!("{:#x}", self.rng.seed()),
+            tick,
+            first_failure_tick: self.failures.first().map(|(tick, _)| tick),
+            config_hash: self.config_hash.clone(),
+            counters: self
+                .statistic
+                .iter()
+                .map(|(name, count)| (name.clone(), *count))
+                .collect(),
+            failures: self
+                .failures
+                .counts()
+                .map(|(kind, count)| (kind.to_string(), count))
+                .collect(),
+        };
+        if let Err(err) = summary.write(&self.run_summary) {
+            error!("{err:#}");
+        }
+    }
+
+    /// Print what each chip select went through, and fail the run if data
+    /// was written to the range of one that never saw a WRITE.
+    fn chip_select_statistics(&mut self) {
+        let mut written = vec![0u64; self.chip_selects.len()];
+        for (addr, _) in self.shadow_mem.written_bytes() {
+            written[self.address_map.decode(addr).cs as usize] += 1;
+        }
+        for (cs, chip_select) in self.chip_selects.iter().enumerate() {
+            let refresh = &chip_select.refresh;
+            info!(
+                "sdram cs{cs}:\n\tbytes written over AXI: {}\n\t{}\n\trefresh count: {}\n\tlongest refresh interval: {} clocks\n\taverage refresh interval required: {:.2} clocks",
+                written[cs],
+                chip_select.coverage,
+                refresh.count(),
+                refresh.longest_gap(),
+                refresh.interval()
+            );
+        }
+        // Without a clock on the pins, as under the TLM, there is nothing
+        // to hold the AXI side to.
+        let missed: Vec<usize> = (0..self.chip_selects.len())
+            .filter(|&cs| {
+                let coverage = &self.chip_selects[cs].coverage;
+                written[cs] > 0 && coverage.clocks() > 0 && coverage.count(Command::Write) == 0
+            })
+            .collect();
+        for cs in missed {
+            driver_error!(
+                self,
+                FailureKind::Sdram,
+                "sdram cs{cs}: {} byte(s) were written to its address range but it saw no WRITE, the controller never selected it",
+                written[cs]
+            );
+        }
+        let unrefreshed: Vec<_> = self
+            .chip_selects
+            .iter()
+            .enumerate()
+            .filter_map(|(cs, chip_select)| Some((cs, chip_select.refresh.finish()?)))
+            .collect();
+        for (cs, violation) in unrefreshed {
+            driver_error!(self, FailureKind::Sdram, "sdram cs{cs}: {violation}");
+        }
+    }
+
+    /// Compare every byte the shadow memory holds a write of against the
+    /// device models, reporting the first `SWEEP_REPORT_LIMIT` divergences
+    /// grouped by row. Catches corruption of data the stimulus never read
+    /// back.
+    fn sweep(&mut self) {
+        if self
+            .chip_selects
+            .iter()
+            .any(|chip_select| chip_select.device.is_none())
+        {
+            info!("end-of-test sweep skipped, no device model to read back");
+            return;
+        }
+
+        for chip_select in &mut self.chip_selects {
+            chip_select.device.as_mut().unwrap().expire();
+        }
+        let injections: Vec<_> = self
+            .chip_selects
+            .iter_mut()
+            .map(|chip_select| chip_select.device.as_mut().unwrap().take_injections())
+            .collect();
+        for (cs, injections) in injections.into_iter().enumerate() {
+            for injection in injections {
+                info!("sdram cs{cs}: fault injected, {injection}");
+            }
+        }
+
+        let written = self.shadow_mem.written_bytes();
+        let mut divergences = Vec::new();
+        let mut expected_corruption = 0;
+        for &(addr, expected) in &written {
+            let sdram = self.address_map.decode(addr);
+            let Some(device) = &self.chip_selects[sdram.cs as usize].device else {
+                continue;
+            };
+            let found = (device.peek(sdram.bank, sdram.row, sdram.col) >> (sdram.byte * 8)) as u8;
+            if found == expected {
+                continue;
+            }
+            if self.faulted(addr, expected, found) {
+                expected_corruption += 1;
+            } else {
+                divergences.push((sdram, addr, expected, found));
+            }
+        }
+        if expected_corruption > 0 {
+            info!(
+                "end-of-test sweep: {expected_corruption} written byte(s) corrupted by injected faults"
+            );
+        }
+        if divergences.is_empty() {
+            info!(
+                "end-of-test sweep: {} written byte(s) match the device",
+                written.len()
+            );
+            return;
+        }
+
+        divergences.sort_by_key(|(sdram, addr, ..)| {
+            (
+                sdram.cs, sdram.bank, sdram.row, sdram.col, sdram.byte, *addr,
+            )
+        });
+        let mut report = String::new();
+        let shown = &divergences[..divergences.len().min(SWEEP_REPORT_LIMIT)];
+        for row in shown.chunk_by(|(a, ..), (b, ..)| (a.cs, a.bank, a.row) == (b.cs, b.bank, b.row))
+        {
+            let (first, ..) = row[0];
+            report += &format!(
+                "\n  cs{}/bank{}/row0x{:x}:",
+                first.cs, first.bank, first.row
+            );
+            for (sdram, addr, expected, found) in row {
+                report += &format!(
+                    "\n    col0x{:x}/byte{} (0x{addr:08x}): expected 0x{expected:02x}, found 0x{found:02x}",
+                    sdram.col, sdram.byte
+                );
+            }
+        }
+        driver_error!(
+            self,
+            FailureKind::Data,
+            "end-of-test sweep: {} of {} written byte(s) diverge from the device, first {} by row:{report}",
+            divergences.len(),
+            written.len(),
+            shown.len()
+        );
+    }
+
     pub(crate) fn axi_write_done(&mut self, bid: u8, bresp: u8, buser: u8) {
         info!("axi_write_done (bid={bid}, bresp={bresp}, buser={buser})");
         *self
             .statistic
             .entry("axi_write_done".to_string())
             .or_insert(0) += 1;
-        let payload = self.axi_write_fifo.pop_front().unwrap();
-        driver_assert_eq!(
-            self,
-            payload.id,
-            bid,
-            "ID is not equal: awid = {}, bid = {}",
-            payload.id,
-            bid
-        );
-        self.shadow_mem.write_mem_axi(payload.clone());
+        self.last_progress = self.get_tick();
+        let Some(payload) = self.outstanding_writes.pop(bid) else {
+            driver_error!(
+                self,
+                FailureKind::Id,
+                "B response with bid = 0x{bid:02x} matches no outstanding write, orphan or duplicate"
+            );
+            return;
+        };
+        if bresp != 0 {
+            driver_error!(
+                self,
+                FailureKind::Response,
+                "write(0x{bid:02x}) got bresp = {bresp}, expected OKAY"
+            );
+        }
+        if let Err(err) = self.shadow_mem.write_mem_axi(payload.clone()) {
+            driver_error!(
+                self,
+                err.kind(),
+                "write(0x{:02x}) failed: {err}",
+                payload.id
+            );
+        }
         self.axi_write_done_fifo.push_back(payload);
     }
 
     pub(crate) fn axi_write_ready(&mut self) -> AxiWritePayload {
         trace!("axi_write_ready");
-        *self.statistic.entry("axi_write".to_string()).or_insert(0) += 1;
-        let payload = AxiWritePayload::random();
-        self.axi_write_fifo.push_back(payload.clone());
+        // a pending read-back only holds writes back while it can be issued
+        if self.next_is_read == Some(true) && self.can_read() {
+            return AxiWritePayload::idle();
+        }
+        if self.outstanding_writes.len() >= self.outstanding {
+            return AxiWritePayload::idle();
+        }
+        let mut payload = match self.pending_write.take() {
+            Some(payload) => payload,
+            None => {
+                if self.next_is_read.is_some() {
+                    self.next_is_read = self.profile.read_next(self.rng.stream(MIX_STREAM));
+                }
+                AxiWritePayload::random(self.config.axi(), &self.profile, &mut self.rng)
+            }
+        };
+        // overlapping transactions in flight would make the read-back ambiguous
+        let footprint = payload.footprint();
+        if self.outstanding_writes.overlaps(&footprint)
+            || self.outstanding_reads.overlaps(&footprint)
+        {
+            self.pending_write = Some(payload);
+            return AxiWritePayload::idle();
+        }
+        let issued = self.statistic.entry("axi_write".to_string()).or_insert(0);
+        *issued += 1;
+        payload.issue_seq = *issued;
+        payload.issue_tick = self.get_tick();
+        self.outstanding_writes.push(payload.clone());
         payload
     }
 
     pub(crate) fn axi_read_ready(&mut self) -> AxiReadPayload {
         trace!("axi_read_ready");
-        if self.axi_write_done_fifo.is_empty() || !self.axi_read_fifo.is_empty() {
-            let mut payload = AxiReadPayload::random();
+        if !self.can_read() || self.next_is_read == Some(false) {
+            let mut payload = AxiReadPayload::random(&mut self.rng);
             payload.valid = 0;
             payload
         } else {
+            if self.next_is_read.is_some() {
+                self.next_is_read = self.profile.read_next(self.rng.stream(MIX_STREAM));
+            }
             *self.statistic.entry("axi_read".to_string()).or_insert(0) += 1;
-            let write_payload = self.axi_write_done_fifo.pop_front().unwrap();
+            let index = self.read_candidate().unwrap();
+            let write_payload = self.axi_write_done_fifo.remove(index).unwrap();
             let payload = AxiReadPayload::from_write_payload(&write_payload);
-            self.axi_read_fifo.push_back(write_payload);
+            self.outstanding_reads.push(write_payload);
             info!(
-                "reading(0x{:02x}) <- 0x{:08x}/{:#} with len = 0x{:02x}",
+                "reading(0x{:02x}) <- 0x{:08x} ({})/{:#} with len = 0x{:02x}",
                 payload.id,
                 payload.addr,
+                self.address_map.decode(payload.addr as u64),
                 match payload.burst {
                     0 => "FIX",
                     1 => "INCR",
@@ -368,6 +765,23 @@ impl Driver {
         }
     }
 
+    /// A read-back can be issued without exceeding the outstanding limit.
+    fn can_read(&self) -> bool {
+        self.outstanding_reads.len() < self.outstanding && self.read_candidate().is_some()
+    }
+
+    /// Oldest completed write no write in flight or held back may overwrite.
+    fn read_candidate(&self) -> Option<usize> {
+        let pending = self.pending_write.as_ref().map(AxiWritePayload::footprint);
+        self.axi_write_done_fifo.iter().position(|payload| {
+            let footprint = payload.footprint();
+            !self.outstanding_writes.overlaps(&footprint)
+                && pending.as_ref().map_or(true, |pending| {
+                    pending.end <= footprint.start || footprint.end <= pending.start
+                })
+        })
+    }
+
     pub(crate) fn axi_read_done(
         &mut self,
         rdata: Vec<u32>,
@@ -385,52 +799,713 @@ impl Driver {
             .statistic
             .entry("axi_read_done".to_string())
             .or_insert(0) += 1;
-        let payload = self.axi_read_fifo.pop_front().unwrap();
-        driver_assert_eq!(
-            self,
-            rid,
-            payload.id,
-            "id is not equal, current: {}, correct: {}",
-            rid,
-            payload.id
-        );
+        self.last_progress = self.get_tick();
+        let Some(payload) = self.outstanding_reads.pop(rid) else {
+            driver_error!(
+                self,
+                FailureKind::Id,
+                "R response with rid = 0x{rid:02x} matches no outstanding read, orphan or duplicate"
+            );
+            return;
+        };
+        if rresp != 0 {
+            driver_error!(
+                self,
+                FailureKind::Response,
+                "read(0x{rid:02x}) got rresp = {rresp}, expected OKAY"
+            );
+        }
+        // the agent counts beats in 8 bits, a full payload wraps to zero
+        let beats = if len == 0 { PAYLOAD_SIZE } else { len as usize };
         driver_assert_eq!(
             self,
-            len,
-            payload.len + 1,
+            FailureKind::Length,
+            beats,
+            payload.len as usize + 1,
             "len is not equal, current: {}, correct: {}",
-            len,
-            payload.len + 1
+            beats,
+            payload.len as usize + 1
         );
-        let compare = self
+        let compare = match self
             .shadow_mem
-            .read_mem_axi(AxiReadPayload::from_write_payload(&payload));
-        let mut vec = rdata[..(len - 1) as usize].to_vec();
+            .read_mem_axi(AxiReadPayload::from_write_payload(&payload))
+        {
+            Ok(compare) => compare,
+            Err(err) => {
+                driver_error!(self, err.kind(), "read(0x{:02x}) failed: {err}", payload.id);
+                return;
+            }
+        };
+        let mut vec = rdata[..beats - 1].to_vec();
         vec.push(last_data);
-        let mut rdata_bytes: Vec<u8> = Vec::new();
-        for idx in 0..len {
-            let strb = payload.strb[idx as usize];
-            let bytes = vec[idx as usize].to_be_bytes().to_vec();
-            let trimed = bytes[bytes.len() - strb.count_ones() as usize..].iter();
-            info!(
-                "trimed: 0x{} -> 0x{}",
-                hex::encode(&bytes),
-                hex::encode(&trimed)
+        let beat_addrs = beat_addresses(payload.addr, payload.burst, payload.len, payload.size);
+        let bus_bytes = self.config.axi().bus_bytes();
+        let mut mismatches = Vec::new();
+        for (beat, ((data, expected), beat_addr)) in
+            vec.into_iter().zip(compare).zip(beat_addrs).enumerate()
+        {
+            trace!(
+                "beat {beat}: 0x{data:08x} & 0x{:08x}, expecting 0x{:08x}",
+                expected.mask(),
+                expected.data
             );
-            rdata_bytes.extend(trimed);
+            if expected.matches(data) {
+                continue;
+            }
+            let bus_addr = (beat_addr / bus_bytes * bus_bytes) as u64;
+            let lane_byte = |value: u32, lane: u32| {
+                ((expected.lanes >> lane) & 1 != 0).then_some((value >> (lane * 8)) as u8)
+            };
+            let bytes = (0..bus_bytes)
+                .filter(|&lane| lane_byte(data ^ expected.data, lane).is_some_and(|xor| xor != 0))
+                .map(|lane| {
+                    let addr = bus_addr + lane as u64;
+                    let (expected, actual) = (
+                        (expected.data >> (lane * 8)) as u8,
+                        (data >> (lane * 8)) as u8,
+                    );
+                    ByteMismatch {
+                        addr,
+                        sdram: self.address_map.decode(addr),
+                        expected,
+                        actual,
+                        writer: self.shadow_mem.last_writer(addr),
+                        faulted: self.faulted(addr, expected, actual),
+                    }
+                })
+                .collect();
+            mismatches.push(BeatMismatch {
+                beat,
+                addr: bus_addr,
+                sdram: self.address_map.decode(bus_addr),
+                expected: (0..bus_bytes)
+                    .map(|lane| lane_byte(expected.data, lane))
+                    .collect(),
+                actual: (0..bus_bytes).map(|lane| lane_byte(data, lane)).collect(),
+                bytes,
+            });
+        }
+        if mismatches.is_empty() {
+            return;
+        }
+
+        let report = MismatchReport {
+            tick: self.get_tick(),
+            id: payload.id,
+            addr: payload.addr,
+            beats,
+            write: WriteRecord::of(&payload),
+            mismatches,
+        };
+        if let Err(err) = report.append(&self.mismatch_report) {
+            error!("{err:#}");
+        }
+        // a read-back only differing on bytes injected faults hit is the
+        // corruption asked for
+        if report.expected_corruption() {
+            info!("{report}\n\texpected from injected faults");
+        } else {
+            driver_error!(self, FailureKind::Data, "{report}");
+        }
+    }
+
+    /// Count a failure, finishing at the next watchdog once the budget is
+    /// spent.
+    fn fail(&mut self, kind: FailureKind) {
+        let tick = self.get_tick();
+        if self.failures.record(kind, tick) {
+            self.dump_manual_finish = true;
+        }
+    }
+
+    /// Whether injected faults may have changed every bit the byte at
+    /// `addr` holds other than `expected`.
+    fn faulted(&self, addr: u64, expected: u8, found: u8) -> bool {
+        let sdram = self.address_map.decode(addr);
+        let bits = self.chip_selects[sdram.cs as usize]
+            .device
+            .as_ref()
+            .and_then(SdramDevice::faults)
+            .map_or(0, |faults| faults.faulted(sdram.bank, sdram.row, sdram.col));
+        (expected ^ found) & !(bits >> (sdram.byte * 8)) as u8 == 0
+    }
+
+    /// Check the signals of the AXI port at a rising edge.
+    pub(crate) fn axi_monitor_tick(&mut self, signals: &AxiSignals) {
+        let tick = self.get_tick();
+        for violation in self.axi_monitor.tick(signals) {
+            driver_error!(self, FailureKind::Axi, "[{tick}] axi: {violation}");
+        }
+    }
+
+    /// Check the pins of chip select `cs` at a rising edge and clock its
+    /// device model, returning what the model drives on DQ. Without a device
+    /// model the pins are only checked.
+    pub(crate) fn sdram_tick(&mut self, cs: usize, pins: &SdramPins) -> u32 {
+        let tick = self.get_tick();
+        let Some(chip_select) = self.chip_selects.get_mut(cs) else {
+            panic!(
+                "no chip select {cs}, the rtl config has {}",
+                self.config.sdram().cs_width
+            );
+        };
+        let command = pins.command();
+        if pins.cke && !matches!(command, Command::Deselect | Command::Nop) {
+            trace!(
+                "[{tick}] sdram cs{cs}: {command:?} bank {} addr 0x{:04x} dqm 0b{:b}",
+                pins.bs,
+                pins.addr,
+                pins.dqm
+            );
+        }
+        chip_select.coverage.tick(pins);
+        let init = chip_select.init.tick(pins);
+        let (address_map, writes) = (&self.address_map, &self.outstanding_writes);
+        let bus_bytes = self.config.axi().bus_bytes();
+        // masked where the write the beat belongs to has no strobe
+        let expected_dqm = |beat: WriteBeat| {
+            let word = address_map.encode(&SdramAddress {
+                cs: cs as u32,
+                bank: beat.bank,
+                row: beat.row,
+                col: beat.col,
+                byte: 0,
+            })?;
+            (0..address_map.word_bytes()).try_fold(0, |dqm, byte| {
+                let strobed = writes.strobed(word + byte, bus_bytes, address_map.capacity())?;
+                Some(dqm | u8::from(!strobed) << byte)
+            })
+        };
+        let mode = chip_select.mode.tick(pins, expected_dqm);
+        if pins.cke && command == Command::LoadMode {
+            if let Ok(mode) = ModeRegister::decode(pins.addr) {
+                info!("[{tick}] sdram cs{cs}: mode register loaded, {mode}");
+            }
+        }
+        let timing = chip_select.timing.tick(pins);
+        let refresh = chip_select.refresh.tick(pins);
+        let device = chip_select.device.as_mut().map(|device| device.tick(pins));
+        let injections = chip_select
+            .device
+            .as_mut()
+            .map_or_else(Vec::new, SdramDevice::take_injections);
+        for injection in injections {
+            info!("[{tick}] sdram cs{cs}: fault injected, {injection}");
+        }
+
+        let mut violations: Vec<String> = init.iter().map(ToString::to_string).collect();
+        violations.extend(mode.iter().map(ToString::to_string));
+        violations.extend(timing.iter().map(ToString::to_string));
+        violations.extend(refresh.iter().map(ToString::to_string));
+        for violation in violations {
+            driver_error!(
+                self,
+                FailureKind::Sdram,
+                "[{tick}] sdram cs{cs}: {violation}"
+            );
+        }
+        match device {
+            Some(Ok(dq)) => dq,
+            Some(Err(err)) => {
+                driver_error!(self, FailureKind::Sdram, "[{tick}] sdram cs{cs}: {err}");
+                0
+            }
+            None => 0,
         }
-        driver_assert_eq!(
-            self,
-            rdata_bytes,
-            compare,
-            "compare failed:\n\tcurrent: {}\n\tcorrect: {}",
-            hex::encode(&rdata_bytes),
-            hex::encode(&compare)
-        );
     }
 
     #[cfg(feature = "trace")]
     fn start_dump_wave(&mut self) {
-        dump_wave(self.scope, &self.wave_path);
+        self.backend.dump_wave(&self.wave_path);
+    }
+}
+
+impl AxiMaster for Driver {
+    fn axi_write_ready(&mut self) -> AxiWritePayload {
+        Driver::axi_write_ready(self)
+    }
+
+    fn axi_write_done(&mut self, bid: u8, bresp: u8, buser: u8) {
+        Driver::axi_write_done(self, bid, bresp, buser)
+    }
+
+    fn axi_read_ready(&mut self) -> AxiReadPayload {
+        Driver::axi_read_ready(self)
+    }
+
+    fn axi_read_done(
+        &mut self,
+        rdata: Vec<u32>,
+        len: u8,
+        last_data: u32,
+        rid: u8,
+        rresp: u8,
+        ruser: u8,
+    ) {
+        Driver::axi_read_done(self, rdata, len, last_data, rid, rresp, ruser)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::MockBackend;
+    use crate::tlm::{TlmConfig, TlmController};
+    use common::CommonArgs;
+
+    const RTL_CONFIG: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../configs/SDRAMControllerTestBenchMain.json"
+    );
+
+    /// A driver over a mock backend, with its reports under a directory of
+    /// its own.
+    fn driver(name: &str, mock: &MockBackend) -> Driver {
+        driver_with(name, mock, |_| {})
+    }
+
+    /// `driver` with the rtl config changed by `configure`.
+    fn driver_with(name: &str, mock: &MockBackend, configure: fn(&mut RTLConfig)) -> Driver {
+        let dir = std::env::temp_dir().join(format!("sdramemu-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |file: &str| Some(dir.join(file).display().to_string());
+        let args = OfflineArgs {
+            common_args: CommonArgs {
+                log_level: "info".into(),
+                rtl_config: RTL_CONFIG.into(),
+            },
+            #[cfg(feature = "trace")]
+            wave_path: String::new(),
+            #[cfg(feature = "trace")]
+            dump_range: String::new(),
+            timeout: Some(100_000),
+            clock_flip_time: Some(1),
+            timescale: None,
+            seed: Some(1),
+            profile: None,
+            outstanding: None,
+            mem_regions: None,
+            sdram_mhz: None,
+            sdram_part: None,
+            sdram_parts: None,
+            sdram_faults: None,
+            sdram_fault: None,
+            mismatch_report: path("mismatch.jsonl"),
+            provenance: None,
+            max_errors: Some(0),
+            transactions: Some(20),
+            hang_ticks: None,
+            run_summary: path("run-summary.json"),
+        };
+        let mut config = RTLConfig::from_file(RTL_CONFIG).unwrap();
+        configure(&mut config);
+        Driver::new(Box::new(mock.clone()), &args, config)
+    }
+
+    /// Clocks between the SDRAM commands of a test, enough for any of them
+    /// to complete at `SDRAM_MHZ`.
+    const GAP: usize = 10;
+
+    /// Clock `pins` into chip select `cs`, then `idle` NOPs, returning what
+    /// the device drove on DQ at every edge.
+    fn issue(driver: &mut Driver, cs: usize, pins: SdramPins, idle: usize) -> Vec<u32> {
+        let nop = SdramPins::encode(Command::Nop, 0, 0);
+        std::iter::once(pins)
+            .chain(std::iter::repeat(nop).take(idle))
+            .map(|pins| driver.sdram_tick(cs, &pins))
+            .collect()
+    }
+
+    /// The power-up sequence of the default part, loading the BL2 CL2 mode
+    /// of the controller.
+    fn power_up(driver: &mut Driver, cs: usize) {
+        let nop = SdramPins::encode(Command::Nop, 0, 0);
+        issue(driver, cs, nop, 100 * SDRAM_MHZ as usize);
+        for (command, addr) in [
+            (Command::Precharge, 1 << 10),
+            (Command::Refresh, 0),
+            (Command::Refresh, 0),
+            (Command::LoadMode, 0x21),
+        ] {
+            issue(driver, cs, SdramPins::encode(command, 0, addr), GAP);
+        }
+    }
+
+    /// Write `data` to the first two columns of `sdram`, which must be in
+    /// an open row.
+    fn write_sdram(driver: &mut Driver, sdram: &SdramAddress, data: u32) {
+        let cs = sdram.cs as usize;
+        let mut write = SdramPins::encode(Command::Write, sdram.bank as u8, sdram.col as u16);
+        write.dq = data & 0xffff;
+        issue(driver, cs, write, 0);
+        let mut beat = SdramPins::encode(Command::Nop, 0, 0);
+        beat.dq = data >> 16;
+        issue(driver, cs, beat, GAP);
+    }
+
+    /// Record a 32-bit AXI write of `data` to `sdram` in the shadow memory.
+    fn write_shadow(driver: &mut Driver, sdram: &SdramAddress, data: u32) {
+        let base = driver.shadow_mem.regions()[0].base;
+        let mut payload = AxiWritePayload::idle();
+        payload.addr = (base + driver.address_map.encode(sdram).unwrap()) as u32;
+        payload.data[0] = data;
+        payload.strb[0] = 0xf;
+        payload.dataValid = 1;
+        payload.burst = 1;
+        payload.size = 2;
+        driver.shadow_mem.write_mem_axi(payload).unwrap();
+    }
+
+    /// Run `master` against the TLM a cycle at a time until the watchdog of
+    /// its driver ends the run.
+    fn run<M: AxiMaster>(
+        config: TlmConfig,
+        master: &mut M,
+        driver: fn(&mut M) -> &mut Driver,
+        mock: &MockBackend,
+    ) -> WatchdogStatus {
+        let mut tlm = TlmController::new(config);
+        loop {
+            tlm.run(master, 1);
+            mock.advance(2);
+            match driver(master).watchdog() {
+                WatchdogStatus::Continue => {}
+                status => return status,
+            }
+        }
+    }
+
+    fn count(driver: &Driver, kind: FailureKind) -> u64 {
+        driver
+            .failures
+            .counts()
+            .find(|&(other, _)| other == kind)
+            .unwrap()
+            .1
+    }
+
+    /// Inverts every read beat on its way back to the driver.
+    struct Corrupt(Driver);
+
+    impl AxiMaster for Corrupt {
+        fn axi_write_ready(&mut self) -> AxiWritePayload {
+            self.0.axi_write_ready()
+        }
+
+        fn axi_write_done(&mut self, bid: u8, bresp: u8, buser: u8) {
+            self.0.axi_write_done(bid, bresp, buser)
+        }
+
+        fn axi_read_ready(&mut self) -> AxiReadPayload {
+            self.0.axi_read_ready()
+        }
+
+        fn axi_read_done(
+            &mut self,
+            rdata: Vec<u32>,
+            len: u8,
+            last_data: u32,
+            rid: u8,
+            rresp: u8,
+            ruser: u8,
+        ) {
+            let rdata = rdata.into_iter().map(|data| !data).collect();
+            self.0
+                .axi_read_done(rdata, len, !last_data, rid, rresp, ruser)
+        }
+    }
+
+    /// Records whether a read response overtook an older read of another ID.
+    struct Overtaken {
+        driver: Driver,
+        /// IDs of the reads issued and not yet answered, oldest first.
+        reads: Vec<u8>,
+        overtaken: bool,
+    }
+
+    impl AxiMaster for Overtaken {
+        fn axi_write_ready(&mut self) -> AxiWritePayload {
+            self.driver.axi_write_ready()
+        }
+
+        fn axi_write_done(&mut self, bid: u8, bresp: u8, buser: u8) {
+            self.driver.axi_write_done(bid, bresp, buser)
+        }
+
+        fn axi_read_ready(&mut self) -> AxiReadPayload {
+            let read = self.driver.axi_read_ready();
+            if read.valid != 0 {
+                self.reads.push(read.id);
+            }
+            read
+        }
+
+        fn axi_read_done(
+            &mut self,
+            rdata: Vec<u32>,
+            len: u8,
+            last_data: u32,
+            rid: u8,
+            rresp: u8,
+            ruser: u8,
+        ) {
+            let index = self.reads.iter().position(|&id| id == rid).unwrap();
+            self.overtaken |= index != 0;
+            self.reads.remove(index);
+            self.driver
+                .axi_read_done(rdata, len, last_data, rid, rresp, ruser)
+        }
+    }
+
+    #[test]
+    fn round_trip_passes() {
+        let mock = MockBackend::default();
+        let mut driver = driver("round-trip", &mock);
+        let status = run(TlmConfig::default(), &mut driver, |driver| driver, &mock);
+        assert_eq!(status, WatchdogStatus::Pass);
+        assert_eq!(mock.status(), Some(WatchdogStatus::Pass));
+        assert_eq!(driver.failures.total(), 0);
+        assert!(driver.statistic["axi_read_done"] >= 20);
+    }
+
+    #[test]
+    fn corrupted_read_fails() {
+        let mock = MockBackend::default();
+        let mut corrupt = Corrupt(driver("corrupt", &mock));
+        let status = run(
+            TlmConfig::default(),
+            &mut corrupt,
+            |corrupt| &mut corrupt.0,
+            &mock,
+        );
+        let driver = corrupt.0;
+        assert_eq!(status, WatchdogStatus::FailMismatch);
+        assert_eq!(mock.status(), Some(WatchdogStatus::FailMismatch));
+        assert_eq!(driver.failures.first().unwrap().1, FailureKind::Data);
+        assert!(count(&driver, FailureKind::Data) > 0);
+    }
+
+    #[test]
+    fn reordered_responses_match_by_id() {
+        let mock = MockBackend::default();
+        let mut master = Overtaken {
+            driver: driver("reorder", &mock),
+            reads: Vec::new(),
+            overtaken: false,
+        };
+        let config = TlmConfig {
+            reorder: true,
+            ..TlmConfig::default()
+        };
+        let status = run(config, &mut master, |master| &mut master.driver, &mock);
+        assert!(master.overtaken, "no response was reordered");
+        assert_eq!(status, WatchdogStatus::Pass);
+        assert_eq!(master.driver.failures.total(), 0);
+    }
+
+    #[test]
+    fn orphan_responses_are_flagged() {
+        let mock = MockBackend::default();
+        let mut driver = driver("orphan", &mock);
+        driver.axi_write_done(3, 0, 0);
+        driver.axi_read_done(vec![0; PAYLOAD_SIZE], 1, 0, 5, 0, 0);
+        assert_eq!(count(&driver, FailureKind::Id), 2);
+        assert_eq!(driver.failures.first().unwrap().1, FailureKind::Id);
+    }
+
+    #[test]
+    fn device_model_backs_the_sweep() {
+        let mock = MockBackend::default();
+        let mut driver = driver_with("device", &mock, |config| config.use_device_model = true);
+        power_up(&mut driver, 0);
+        let sdram = SdramAddress {
+            cs: 0,
+            bank: 1,
+            row: 3,
+            col: 4,
+            byte: 0,
+        };
+        write_shadow(&mut driver, &sdram, 0x1234_5678);
+        let active = SdramPins::encode(Command::Active, 1, 3);
+        issue(&mut driver, 0, active, GAP);
+        write_sdram(&mut driver, &sdram, 0x1234_5678);
+        let read = SdramPins::encode(Command::Read, 1, 4);
+        let dq = issue(&mut driver, 0, read, GAP);
+        let dq: Vec<u32> = dq.into_iter().filter(|&dq| dq != 0).collect();
+        assert_eq!(dq, [0x5678, 0x1234]);
+        let precharge = SdramPins::encode(Command::Precharge, 0, 1 << 10);
+        issue(&mut driver, 0, precharge, GAP);
+
+        driver.chip_select_statistics();
+        driver.sweep();
+        assert_eq!(driver.failures.total(), 0);
+        let coverage = &driver.chip_selects[0].coverage;
+        assert_eq!(coverage.count(Command::Write), 1);
+        assert_eq!(coverage.count(Command::Read), 1);
+
+        // a write the device never saw
+        let lost = SdramAddress { col: 6, ..sdram };
+        write_shadow(&mut driver, &lost, 0xff);
+        driver.sweep();
+        assert_eq!(count(&driver, FailureKind::Data), 1);
+
+        // the device drops a READ of a bank with no open row
+        issue(&mut driver, 0, read, GAP);
+        assert_eq!(count(&driver, FailureKind::Sdram), 1);
+    }
+
+    #[test]
+    fn chip_selects_are_covered_apart() {
+        let mock = MockBackend::default();
+        let mut driver = driver_with("chip-selects", &mock, |config| {
+            config.use_device_model = true;
+            config.sdram_controller_parameter.sdram_parameter.cs_width = 2;
+        });
+        for cs in 0..2 {
+            power_up(&mut driver, cs);
+        }
+        let selected = SdramAddress {
+            cs: 0,
+            bank: 2,
+            row: 1,
+            col: 8,
+            byte: 0,
+        };
+        let skipped = SdramAddress { cs: 1, ..selected };
+        write_shadow(&mut driver, &selected, 0xdead_beef);
+        write_shadow(&mut driver, &skipped, 0xcafe_f00d);
+        // the range of cs1 starts where cs0 ends
+        let base = driver.shadow_mem.regions()[0].base;
+        let ranges = driver
+            .address_map
+            .cs_ranges(base..base + driver.address_map.capacity());
+        assert_eq!(ranges.len(), 2);
+        assert_eq!(ranges[1].0, 1);
+        assert_eq!(ranges[1].1.start, base + driver.address_map.cs_bytes());
+
+        // only cs0 is written to
+        let active = SdramPins::encode(Command::Active, 2, 1);
+        issue(&mut driver, 0, active, GAP);
+        write_sdram(&mut driver, &selected, 0xdead_beef);
+        let precharge = SdramPins::encode(Command::Precharge, 0, 1 << 10);
+        issue(&mut driver, 0, precharge, GAP);
+
+        driver.chip_select_statistics();
+        assert_eq!(count(&driver, FailureKind::Sdram), 1);
+        let coverage: Vec<_> = driver
+            .chip_selects
+            .iter()
+            .map(|chip_select| chip_select.coverage.count(Command::Write))
+            .collect();
+        assert_eq!(coverage, [1, 0]);
+        assert_eq!(driver.chip_selects[1].coverage.count(Command::LoadMode), 1);
+
+        driver.sweep();
+        assert_eq!(count(&driver, FailureKind::Data), 1);
+    }
+
+    #[test]
+    fn faults_excuse_only_the_bits_they_hit() {
+        let mock = MockBackend::default();
+        let mut driver = driver_with("fault-bits", &mock, |config| config.use_device_model = true);
+        let sdram = SdramAddress {
+            cs: 0,
+            bank: 1,
+            row: 3,
+            col: 4,
+            byte: 0,
+        };
+        let stuck = Fault::StuckAt {
+            cell: sdram,
+            bit: 0,
+            value: true,
+        };
+        let geometry = driver.address_map.geometry().to_owned();
+        let faults = FaultModel::new(geometry, [&stuck], SDRAM_MHZ, 1).unwrap();
+        let chip_select = &mut driver.chip_selects[0];
+        chip_select.device.as_mut().unwrap().inject(faults);
+        power_up(&mut driver, 0);
+        let active = SdramPins::encode(Command::Active, 1, 3);
+        issue(&mut driver, 0, active, GAP);
+        write_sdram(&mut driver, &sdram, 0x1234_5678);
+        let precharge = SdramPins::encode(Command::Precharge, 0, 1 << 10);
+        issue(&mut driver, 0, precharge, GAP);
+
+        // 0x79 found for 0x78, the stuck bit
+        write_shadow(&mut driver, &sdram, 0x1234_5678);
+        driver.sweep();
+        assert_eq!(driver.failures.total(), 0);
+        // 0x79 found for 0x76, three more bits
+        write_shadow(&mut driver, &sdram, 0x1234_5676);
+        driver.sweep();
+        assert_eq!(count(&driver, FailureKind::Data), 1);
+    }
+
+    #[test]
+    fn mismatches_name_the_write_of_each_byte() {
+        let mock = MockBackend::default();
+        let mut driver = driver("provenance", &mock);
+        let _ = std::fs::remove_file(&driver.mismatch_report);
+        let base = driver.shadow_mem.regions()[0].base as u32;
+        let mut first = AxiWritePayload::idle();
+        first.id = 1;
+        first.issue_seq = 1;
+        first.issue_tick = 10;
+        first.addr = base + 0x100;
+        first.data[..2].copy_from_slice(&[0x4433_2211, 0x8877_6655]);
+        first.strb[..2].copy_from_slice(&[0xf, 0xf]);
+        first.dataValid = 1;
+        first.burst = 1;
+        first.size = 2;
+        first.len = 1;
+        driver.shadow_mem.write_mem_axi(first.clone()).unwrap();
+        // byte 2 of the first word and byte 0 of the second overwritten,
+        // byte 3 left out of its halfword by the strobes
+        let mut second = first.clone();
+        second.id = 2;
+        second.issue_seq = 2;
+        second.issue_tick = 20;
+        second.addr = base + 0x102;
+        second.data[..2].copy_from_slice(&[0x00cc_0000, 0x0000_00dd]);
+        second.strb[..2].copy_from_slice(&[0x4, 0x1]);
+        second.size = 1;
+        driver.shadow_mem.write_mem_axi(second).unwrap();
+
+        // the read-back of the first write returns zeros
+        driver.outstanding_reads.push(first);
+        driver.axi_read_done(vec![0], 2, 0, 1, 0, 0);
+        assert_eq!(count(&driver, FailureKind::Data), 1);
+
+        let line = std::fs::read_to_string(&driver.mismatch_report).unwrap();
+        let report: serde_json::Value = serde_json::from_str(line.trim_end()).unwrap();
+        assert_eq!(report["write"]["seq"], 1);
+        let writers: Vec<(u64, u64, u64, u64)> = report["mismatches"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .flat_map(|beat| beat["bytes"].as_array().unwrap())
+            .map(|byte| {
+                let writer = &byte["writer"];
+                (
+                    byte["addr"].as_u64().unwrap() - base as u64,
+                    writer["seq"].as_u64().unwrap(),
+                    writer["issueTick"].as_u64().unwrap(),
+                    writer["beat"].as_u64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            writers,
+            [
+                (0x100, 1, 10, 0),
+                (0x101, 1, 10, 0),
+                (0x102, 2, 20, 0),
+                (0x103, 1, 10, 0),
+                (0x104, 2, 20, 1),
+                (0x105, 1, 10, 1),
+                (0x106, 1, 10, 1),
+                (0x107, 1, 10, 1),
+            ]
+        );
     }
 }
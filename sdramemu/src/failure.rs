@@ -0,0 +1,120 @@
+//! Failures of the design, counted by category so that a run given
+//! `+max-errors=<n>` can go on past the first one and summarize them at the
+//! end.
+
+use std::fmt;
+
+use crate::backend::WatchdogStatus;
+
+/// Stop at the first failure when `+max-errors=` is not given.
+pub(crate) const DEFAULT_MAX_ERRORS: u64 = 1;
+
+/// What a failure was detected on, in the order the summary lists them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FailureKind {
+    /// A response whose ID matches no transaction in flight.
+    Id,
+    /// A read response with the wrong number of beats.
+    Length,
+    /// Data read back differently from what was written.
+    Data,
+    /// A response other than OKAY.
+    Response,
+    /// A transaction outside the mapped memory regions.
+    Range,
+    /// A violation on the SDRAM pins, or of the device model.
+    Sdram,
+    /// A violation of the AXI4 protocol on the controller's port.
+    Axi,
+}
+
+impl FailureKind {
+    /// How a run whose first failure is of this kind ends.
+    pub(crate) fn status(self) -> WatchdogStatus {
+        match self {
+            FailureKind::Data | FailureKind::Range => WatchdogStatus::FailMismatch,
+            FailureKind::Id
+            | FailureKind::Length
+            | FailureKind::Response
+            | FailureKind::Sdram
+            | FailureKind::Axi => WatchdogStatus::FailProtocol,
+        }
+    }
+}
+
+const KINDS: [FailureKind; 7] = [
+    FailureKind::Id,
+    FailureKind::Length,
+    FailureKind::Data,
+    FailureKind::Response,
+    FailureKind::Range,
+    FailureKind::Sdram,
+    FailureKind::Axi,
+];
+
+impl fmt::Display for FailureKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FailureKind::Id => "id",
+            FailureKind::Length => "length",
+            FailureKind::Data => "data",
+            FailureKind::Response => "response",
+            FailureKind::Range => "range",
+            FailureKind::Sdram => "sdram",
+            FailureKind::Axi => "axi",
+        })
+    }
+}
+
+pub(crate) struct Failures {
+    /// Failures the run stops after, 0 for no limit.
+    max: u64,
+    counts: [u64; KINDS.len()],
+    /// Tick and kind of the first failure.
+    first: Option<(u64, FailureKind)>,
+}
+
+impl Failures {
+    pub(crate) fn new(max: u64) -> Self {
+        Self {
+            max,
+            counts: [0; KINDS.len()],
+            first: None,
+        }
+    }
+
+    /// Count a failure, returns whether the run should stop.
+    pub(crate) fn record(&mut self, kind: FailureKind, tick: u64) -> bool {
+        self.first.get_or_insert((tick, kind));
+        let index = KINDS.iter().position(|&other| other == kind).unwrap();
+        self.counts[index] += 1;
+        self.max != 0 && self.total() >= self.max
+    }
+
+    pub(crate) fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    pub(crate) fn first(&self) -> Option<(u64, FailureKind)> {
+        self.first
+    }
+
+    /// Count of every kind, in summary order.
+    pub(crate) fn counts(&self) -> impl Iterator<Item = (FailureKind, u64)> + '_ {
+        KINDS.iter().copied().zip(self.counts)
+    }
+}
+
+impl fmt::Display for Failures {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failures: {}", self.total())?;
+        match self.max {
+            0 => write!(f, " (no limit)")?,
+            max => write!(f, " (stopping at {max})")?,
+        }
+        for (kind, count) in self.counts() {
+            write!(f, "\n\t{kind}: {count}")?;
+        }
+        Ok(())
+    }
+}
@@ -1,6 +1,15 @@
 use common::{plusarg::PlusArgMatcher, CommonArgs};
+pub mod axi;
+pub mod backend;
 pub mod dpi;
 pub mod drive;
+mod failure;
+mod profile;
+mod report;
+pub mod sdram;
+mod shadow_mem;
+mod summary;
+pub mod tlm;
 
 pub(crate) struct OfflineArgs {
     pub common_args: CommonArgs,
@@ -10,9 +19,73 @@ pub(crate) struct OfflineArgs {
 
     #[cfg(feature = "trace")]
     pub dump_range: String,
+
+    /// Watchdog timeout in ticks, overrides the rtl config
+    pub timeout: Option<u64>,
+
+    /// Simulator time units per clock flip, overrides `clockFlipTick * timescale`
+    pub clock_flip_time: Option<u64>,
+
+    /// Simulator time units per `clockFlipTick` unit
+    pub timescale: Option<u64>,
+
+    /// Seed of every random stimulus stream, defaults to the wall clock
+    pub seed: Option<u64>,
+
+    /// Path to a stimulus profile, see `profile.rs`
+    pub profile: Option<String>,
+
+    /// Transactions in flight per direction, defaults to `AGENT_OUTSTANDING`
+    pub outstanding: Option<u64>,
+
+    /// Mapped memory regions as `<base>:<size>[,...]`, see `shadow_mem.rs`
+    pub mem_regions: Option<String>,
+
+    /// SDRAM clock the timing checks convert ns at, defaults to `SDRAM_MHZ`
+    pub sdram_mhz: Option<u64>,
+
+    /// SDRAM part behind every chip select, see `sdram/part.rs`
+    pub sdram_part: Option<String>,
+
+    /// JSON file of SDRAM parts to add to the built-in ones
+    pub sdram_parts: Option<String>,
+
+    /// File of faults to inject into the device model, see `sdram/fault.rs`
+    pub sdram_faults: Option<String>,
+
+    /// Faults to inject into the device model, comma separated
+    pub sdram_fault: Option<String>,
+
+    /// JSON lines file mismatch reports are appended to, see `report.rs`
+    pub mismatch_report: Option<String>,
+
+    /// Which write each shadow memory byte comes from: `byte`, `word` or `off`
+    pub provenance: Option<String>,
+
+    /// Failures to stop after, 0 for no limit, defaults to `DEFAULT_MAX_ERRORS`
+    pub max_errors: Option<u64>,
+
+    /// Read-backs to pass after, otherwise the run goes on to the timeout
+    pub transactions: Option<u64>,
+
+    /// Ticks without a completion a run is deemed hung after, 0 to never,
+    /// defaults to `DEFAULT_HANG_TICKS`
+    pub hang_ticks: Option<u64>,
+
+    /// JSON file the run summary is written to, see `summary.rs`
+    pub run_summary: Option<String>,
 }
 
-pub const AXI_SIZE: u8 = 32;
+/// Number of beats the AXI4MasterAgent payload buffers can hold, see
+/// `readPayloadSize`/`writePayloadSize` in the testbench.
+pub const PAYLOAD_SIZE: usize = 256;
+
+/// Depth of the AXI4MasterAgent request queues, see `outstanding` in the
+/// testbench.
+pub const AGENT_OUTSTANDING: usize = 4;
+
+/// Clock of the SDRAM interface, see `SDRAM_MHZ` in the controller.
+pub const SDRAM_MHZ: u64 = 100;
 
 impl OfflineArgs {
     pub fn from_plusargs(matcher: &PlusArgMatcher) -> Self {
@@ -22,24 +95,78 @@ impl OfflineArgs {
             dump_range: matcher.match_("dump-range").into(),
             #[cfg(feature = "trace")]
             wave_path: matcher.match_("wave-path").into(),
+            timeout: try_match_u64(matcher, "timeout"),
+            clock_flip_time: try_match_u64(matcher, "clock-flip-time"),
+            timescale: try_match_u64(matcher, "timescale"),
+            seed: try_match_u64(matcher, "seed"),
+            profile: matcher.try_match("profile").map(String::from),
+            outstanding: try_match_u64(matcher, "outstanding"),
+            mem_regions: matcher.try_match("mem-regions").map(String::from),
+            sdram_mhz: try_match_u64(matcher, "sdram-mhz"),
+            sdram_part: matcher.try_match("sdram-part").map(String::from),
+            sdram_parts: matcher.try_match("sdram-parts").map(String::from),
+            sdram_faults: matcher.try_match("sdram-faults").map(String::from),
+            sdram_fault: matcher.try_match("sdram-fault").map(String::from),
+            mismatch_report: matcher.try_match("mismatch-report").map(String::from),
+            provenance: matcher.try_match("provenance").map(String::from),
+            max_errors: try_match_u64(matcher, "max-errors"),
+            transactions: try_match_u64(matcher, "transactions"),
+            hang_ticks: try_match_u64(matcher, "hang-ticks"),
+            run_summary: matcher.try_match("run-summary").map(String::from),
         }
     }
 }
 
+fn try_match_u64(matcher: &PlusArgMatcher, arg_name: &str) -> Option<u64> {
+    matcher.try_match(arg_name).map(|value| {
+        parse_number(value)
+            .unwrap_or_else(|_| panic!("invalid number for '+{arg_name}=': `{value}`"))
+    })
+}
+
+/// Parse a decimal or `0x` prefixed hex number.
+pub fn parse_number(value: &str) -> Result<u64, std::num::ParseIntError> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => value.parse(),
+    }
+}
+
+/// FNV-1a of `bytes`, its offset basis keyed by `key`. Unlike `DefaultHasher`
+/// it is stable across toolchains and builds, so hashes and seeds derived
+/// from it can be compared between runs.
+pub(crate) fn fnv1a(key: u64, bytes: impl IntoIterator<Item = u8>) -> u64 {
+    bytes
+        .into_iter()
+        .fold(0xcbf2_9ce4_8422_2325 ^ key, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+/// Report a failure of the design, stopping the simulation at the next
+/// watchdog once `+max-errors=` of them are counted.
+#[macro_export]
+macro_rules! driver_error {
+    ($self:expr, $kind:expr, $($arg:tt)+) => {{
+        $self.fail($kind);
+        error!($($arg)+);
+    }};
+}
+
 #[macro_export]
 macro_rules! driver_assert_eq {
-    ($self:expr, $left:expr, $right:expr $(,)?) => {{
+    ($self:expr, $kind:expr, $left:expr, $right:expr $(,)?) => {{
         if $left != $right {
-            $self.dump_manual_finish = true;
+            $self.fail($kind);
             error!(
                 "assertion failed: `(left == right)`\n  left: `{:?}`\n right: `{:?}`",
                 $left, $right
             );
         }
     }};
-    ($self:expr, $left:expr, $right:expr, $($arg:tt)+) => {{
+    ($self:expr, $kind:expr, $left:expr, $right:expr, $($arg:tt)+) => {{
         if $left != $right {
-            $self.dump_manual_finish = true;
+            $self.fail($kind);
             error!($($arg)+);
         }
     }};
@@ -0,0 +1,457 @@
+//! Constrained-random stimulus profiles, selected with `+profile=<path>`.
+//!
+//! A profile is a JSON file describing the distribution of every AXI field the
+//! generator drives. Every key is optional, a missing one keeps the built-in
+//! default:
+//!
+//! ```json
+//! {
+//!   "readWriteMix": { "read": 1, "write": 3 },
+//!   "burst": { "fixed": 1, "incr": 3, "wrap": 1 },
+//!   "len": {
+//!     "fixed": [{ "min": 1, "max": 16 }],
+//!     "incr": [{ "min": 1, "max": 8, "weight": 9 }, { "min": 256, "weight": 1 }],
+//!     "wrap": [{ "min": 4 }, { "min": 8 }]
+//!   },
+//!   "size": [{ "min": 0, "max": 1 }],
+//!   "strb": { "full": 4, "sparse": 1 },
+//!   "align": { "aligned": 3, "unaligned": 1 },
+//!   "addr": [{ "base": "0xfc000000", "size": "0x400", "weight": 1 }],
+//!   "id": [{ "min": 0, "max": 3 }],
+//!   "cache": [{ "min": 0 }]
+//! }
+//! ```
+//!
+//! `len` counts beats, `size` is AxSIZE. Ranges are inclusive and `max`
+//! defaults to `min`, `weight` defaults to 1.
+
+use anyhow::{bail, ensure, Context, Result};
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::Rng;
+use serde::{Deserialize, Deserializer};
+use std::ops::RangeInclusive;
+
+use crate::parse_number;
+use crate::shadow_mem::MemRegion;
+use std::path::Path;
+
+/// Bursts are kept inside aligned blocks of this size, as INCR bursts must not
+/// cross a 4KB boundary.
+pub(crate) const AXI_BOUNDARY: u32 = 4096;
+
+fn default_weight() -> u32 {
+    1
+}
+
+/// Accept either a JSON number or a `0x` prefixed hex string.
+fn deserialize_number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Number {
+        Int(u64),
+        Str(String),
+    }
+    match Number::deserialize(deserializer)? {
+        Number::Int(value) => Ok(value),
+        Number::Str(value) => parse_number(&value)
+            .map_err(|_| serde::de::Error::custom(format!("invalid number `{value}`"))),
+    }
+}
+
+fn deserialize_option_number<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u64>, D::Error> {
+    #[derive(Deserialize)]
+    struct Wrapper(#[serde(deserialize_with = "deserialize_number")] u64);
+    Ok(Option::<Wrapper>::deserialize(deserializer)?.map(|Wrapper(value)| value))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct WeightedRange {
+    #[serde(deserialize_with = "deserialize_number")]
+    min: u64,
+    #[serde(default, deserialize_with = "deserialize_option_number")]
+    max: Option<u64>,
+    #[serde(default = "default_weight")]
+    weight: u32,
+}
+
+impl WeightedRange {
+    fn range(&self) -> RangeInclusive<u64> {
+        self.min..=self.max.unwrap_or(self.min)
+    }
+}
+
+/// Weighted union of inclusive ranges.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct FieldDist(Vec<WeightedRange>);
+
+impl FieldDist {
+    fn constant(value: u64) -> Self {
+        Self::range(value, value)
+    }
+
+    fn range(min: u64, max: u64) -> Self {
+        Self(vec![WeightedRange {
+            min,
+            max: Some(max),
+            weight: 1,
+        }])
+    }
+
+    pub(crate) fn sample(&self, rng: &mut StdRng) -> u64 {
+        let index = WeightedIndex::new(self.0.iter().map(|entry| entry.weight))
+            .unwrap()
+            .sample(rng);
+        rng.gen_range(self.0[index].range())
+    }
+
+    /// Check every value the distribution can produce with `legal`.
+    fn validate(&self, name: &str, legal: impl Fn(u64) -> bool) -> Result<()> {
+        ensure!(!self.0.is_empty(), "`{name}` has no choices");
+        ensure!(
+            self.0.iter().any(|entry| entry.weight > 0),
+            "`{name}` has no choice with a non-zero weight"
+        );
+        for entry in &self.0 {
+            let range = entry.range();
+            ensure!(!range.is_empty(), "`{name}` has an empty range {range:?}");
+            // ranges of beats and sizes are tiny, wide ones only need their bounds checked
+            if range.end() - range.start() <= 256 {
+                if let Some(value) = range.clone().find(|&value| !legal(value)) {
+                    bail!("`{name}` can produce illegal value {value}");
+                }
+            } else {
+                ensure!(
+                    legal(*range.start()) && legal(*range.end()),
+                    "`{name}` range {range:?} is out of bounds"
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct AddrWindow {
+    #[serde(deserialize_with = "deserialize_number")]
+    base: u64,
+    #[serde(deserialize_with = "deserialize_number")]
+    size: u64,
+    #[serde(default = "default_weight")]
+    weight: u32,
+}
+
+impl AddrWindow {
+    pub(crate) fn range(&self) -> RangeInclusive<u64> {
+        self.base..=self.base + self.size - 1
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BurstType {
+    Fixed = 0,
+    Incr = 1,
+    Wrap = 2,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct BurstWeights {
+    fixed: u32,
+    incr: u32,
+    wrap: u32,
+}
+
+impl Default for BurstWeights {
+    fn default() -> Self {
+        Self {
+            fixed: 1,
+            incr: 3,
+            wrap: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct BurstLength {
+    fixed: FieldDist,
+    incr: FieldDist,
+    wrap: FieldDist,
+}
+
+impl Default for BurstLength {
+    fn default() -> Self {
+        Self {
+            fixed: FieldDist::range(1, 16),
+            incr: FieldDist::range(1, 256),
+            wrap: FieldDist(
+                [2, 4, 8, 16]
+                    .into_iter()
+                    .map(|beats| WeightedRange {
+                        min: beats,
+                        max: None,
+                        weight: 1,
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct StrobeWeights {
+    /// every byte lane of the transfer
+    full: u32,
+    /// a random subset of the lanes of the transfer, possibly none
+    sparse: u32,
+}
+
+impl Default for StrobeWeights {
+    fn default() -> Self {
+        Self { full: 1, sparse: 0 }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct AlignWeights {
+    /// start at a multiple of the transfer size
+    aligned: u32,
+    /// start anywhere inside the first transfer, never used for WRAP bursts
+    unaligned: u32,
+}
+
+impl Default for AlignWeights {
+    fn default() -> Self {
+        Self {
+            aligned: 1,
+            unaligned: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ReadWriteMix {
+    read: u32,
+    write: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+pub(crate) struct Profile {
+    /// Relative weights of issuing a read-back against a new write. Reads
+    /// only target completed writes, so they never outnumber writes.
+    read_write_mix: Option<ReadWriteMix>,
+    burst: BurstWeights,
+    len: BurstLength,
+    size: Option<FieldDist>,
+    strb: StrobeWeights,
+    align: AlignWeights,
+    /// Empty means every mapped memory region.
+    addr: Vec<AddrWindow>,
+    /// Unset means IDs are handed out sequentially.
+    id: Option<FieldDist>,
+    cache: FieldDist,
+    lock: FieldDist,
+    prot: FieldDist,
+    qos: FieldDist,
+    region: FieldDist,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            read_write_mix: None,
+            burst: BurstWeights::default(),
+            len: BurstLength::default(),
+            size: None,
+            strb: StrobeWeights::default(),
+            align: AlignWeights::default(),
+            addr: Vec::new(),
+            id: None,
+            // the controller does not support these, drive recognizable junk
+            cache: FieldDist::constant(0x77),
+            lock: FieldDist::constant(0x88),
+            prot: FieldDist::constant(0x99),
+            qos: FieldDist::constant(0xaa),
+            region: FieldDist::constant(0xbb),
+        }
+    }
+}
+
+impl Profile {
+    pub(crate) fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read profile `{}`", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("failed to parse profile `{}`", path.display()))
+    }
+
+    /// Check the profile only produces legal transactions for a bus with
+    /// `max_size` and a memory mapping `regions`, then fill in the defaults
+    /// that depend on them.
+    pub(crate) fn resolve(&mut self, max_size: u8, regions: &[MemRegion]) -> Result<()> {
+        let burst = &self.burst;
+        ensure!(
+            burst.fixed + burst.incr + burst.wrap > 0,
+            "`burst` has no burst type with a non-zero weight"
+        );
+        self.len
+            .fixed
+            .validate("len.fixed", |beats| (1..=16).contains(&beats))?;
+        self.len
+            .incr
+            .validate("len.incr", |beats| (1..=256).contains(&beats))?;
+        self.len
+            .wrap
+            .validate("len.wrap", |beats| matches!(beats, 2 | 4 | 8 | 16))?;
+
+        let size = self
+            .size
+            .get_or_insert_with(|| FieldDist::range(0, max_size as u64));
+        size.validate("size", |size| size <= max_size as u64)?;
+
+        ensure!(
+            self.strb.full + self.strb.sparse > 0,
+            "`strb` has no pattern with a non-zero weight"
+        );
+        ensure!(
+            self.align.aligned + self.align.unaligned > 0,
+            "`align` has no alignment with a non-zero weight"
+        );
+        if let Some(mix) = &self.read_write_mix {
+            ensure!(mix.write > 0, "`readWriteMix` must allow writes");
+        }
+        if let Some(id) = &self.id {
+            id.validate("id", |id| id <= u8::MAX as u64)?;
+        }
+        for (name, field) in [
+            ("cache", &self.cache),
+            ("lock", &self.lock),
+            ("prot", &self.prot),
+            ("qos", &self.qos),
+            ("region", &self.region),
+        ] {
+            field.validate(name, |value| value <= u8::MAX as u64)?;
+        }
+
+        if self.addr.is_empty() {
+            self.addr.extend(regions.iter().map(|region| AddrWindow {
+                base: region.base,
+                size: region.size,
+                weight: 1,
+            }));
+        }
+        for window in &self.addr {
+            // a WRAP container is at most 16 beats of the full bus width
+            let granule = 16u64 << max_size;
+            ensure!(
+                window.size > 0 && window.base % granule == 0 && window.size % granule == 0,
+                "address window {:#x}+{:#x} must be {granule} bytes aligned",
+                window.base,
+                window.size
+            );
+            ensure!(
+                regions
+                    .iter()
+                    .any(|region| region.contains(window.base, window.size)),
+                "address window {:#x}+{:#x} is outside the mapped memory",
+                window.base,
+                window.size
+            );
+        }
+        ensure!(
+            self.addr.iter().any(|window| window.weight > 0),
+            "`addr` has no window with a non-zero weight"
+        );
+        Ok(())
+    }
+
+    /// Whether the next transaction should be a read-back, `None` if no mix is
+    /// configured and reads are issued whenever possible.
+    pub(crate) fn read_next(&self, rng: &mut StdRng) -> Option<bool> {
+        self.read_write_mix
+            .as_ref()
+            .map(|mix| rng.gen_ratio(mix.read, mix.read + mix.write))
+    }
+
+    pub(crate) fn burst_type(&self, rng: &mut StdRng) -> BurstType {
+        let burst = &self.burst;
+        match WeightedIndex::new([burst.fixed, burst.incr, burst.wrap])
+            .unwrap()
+            .sample(rng)
+        {
+            0 => BurstType::Fixed,
+            1 => BurstType::Incr,
+            _ => BurstType::Wrap,
+        }
+    }
+
+    /// Number of beats of a burst.
+    pub(crate) fn beats(&self, burst: BurstType, rng: &mut StdRng) -> u32 {
+        match burst {
+            BurstType::Fixed => self.len.fixed.sample(rng) as u32,
+            BurstType::Incr => self.len.incr.sample(rng) as u32,
+            BurstType::Wrap => self.len.wrap.sample(rng) as u32,
+        }
+    }
+
+    pub(crate) fn size(&self, rng: &mut StdRng) -> u8 {
+        self.size
+            .as_ref()
+            .expect("profile is not resolved")
+            .sample(rng) as u8
+    }
+
+    pub(crate) fn addr(&self, rng: &mut StdRng) -> u32 {
+        let index = WeightedIndex::new(self.addr.iter().map(|window| window.weight))
+            .unwrap()
+            .sample(rng);
+        rng.gen_range(self.addr[index].range()) as u32
+    }
+
+    /// End of the address window `addr` belongs to, exclusive.
+    pub(crate) fn window_end(&self, addr: u32) -> u64 {
+        self.addr
+            .iter()
+            .map(AddrWindow::range)
+            .find(|range| range.contains(&(addr as u64)))
+            .map_or(addr as u64 + 1, |range| range.end() + 1)
+    }
+
+    /// Pick the strobe of a transfer whose active byte lanes are `lanes`.
+    pub(crate) fn strb(&self, lanes: u8, rng: &mut StdRng) -> u8 {
+        if rng.gen_ratio(self.strb.sparse, self.strb.full + self.strb.sparse) {
+            lanes & rng.gen::<u8>()
+        } else {
+            lanes
+        }
+    }
+
+    /// Whether a FIXED or INCR burst should start at an unaligned address.
+    pub(crate) fn unaligned(&self, rng: &mut StdRng) -> bool {
+        rng.gen_ratio(
+            self.align.unaligned,
+            self.align.aligned + self.align.unaligned,
+        )
+    }
+
+    pub(crate) fn id(&self, rng: &mut StdRng) -> Option<u8> {
+        self.id.as_ref().map(|id| id.sample(rng) as u8)
+    }
+
+    /// AxCACHE, AxLOCK, AxPROT, AxQOS and AxREGION in that order.
+    pub(crate) fn attributes(&self, rng: &mut StdRng) -> [u8; 5] {
+        [&self.cache, &self.lock, &self.prot, &self.qos, &self.region]
+            .map(|field| field.sample(rng) as u8)
+    }
+}
@@ -0,0 +1,160 @@
+//! Reports of read-backs that did not return what was written, logged as a
+//! table and appended as one JSON object per line to the file given with
+//! `+mismatch-report=<path>`, `mismatches.jsonl` by default.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+
+use crate::sdram::SdramAddress;
+use crate::shadow_mem::{Origin, WriteRecord};
+
+/// Where reports go when `+mismatch-report=` is not given.
+pub(crate) const DEFAULT_REPORT_PATH: &str = "mismatches.jsonl";
+
+/// A byte of a beat that read back wrong.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ByteMismatch {
+    pub(crate) addr: u64,
+    pub(crate) sdram: SdramAddress,
+    pub(crate) expected: u8,
+    pub(crate) actual: u8,
+    /// The write the expected value comes from, if provenance is kept.
+    pub(crate) writer: Option<Origin>,
+    /// Whether the bits injected faults hit explain the difference.
+    pub(crate) faulted: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BeatMismatch {
+    pub(crate) beat: usize,
+    /// Address of the beat on the bus, i.e. of byte lane 0.
+    pub(crate) addr: u64,
+    pub(crate) sdram: SdramAddress,
+    /// Expected and actual data of each byte lane, `None` on inactive lanes.
+    pub(crate) expected: Vec<Option<u8>>,
+    pub(crate) actual: Vec<Option<u8>>,
+    pub(crate) bytes: Vec<ByteMismatch>,
+}
+
+impl BeatMismatch {
+    /// Difference of every byte lane, `None` on inactive ones.
+    pub(crate) fn xor(&self) -> Vec<Option<u8>> {
+        self.expected
+            .iter()
+            .zip(&self.actual)
+            .map(|(expected, actual)| Some(expected.as_ref()? ^ actual.as_ref()?))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct MismatchReport {
+    /// Tick the read response completed at.
+    pub(crate) tick: u64,
+    pub(crate) id: u8,
+    pub(crate) addr: u32,
+    pub(crate) beats: usize,
+    /// The write the read-back was issued for.
+    pub(crate) write: WriteRecord,
+    pub(crate) mismatches: Vec<BeatMismatch>,
+}
+
+impl MismatchReport {
+    /// Whether injected faults explain every mismatching byte.
+    pub(crate) fn expected_corruption(&self) -> bool {
+        self.mismatches
+            .iter()
+            .flat_map(|beat| &beat.bytes)
+            .all(|byte| byte.faulted)
+    }
+
+    /// Append the report to a JSON lines file.
+    pub(crate) fn append(&self, path: &Path) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("failed to open mismatch report `{}`", path.display()))?;
+        let mut line = serde_json::to_string(self)?;
+        line.push('\n');
+        file.write_all(line.as_bytes())
+            .with_context(|| format!("failed to write mismatch report `{}`", path.display()))
+    }
+}
+
+/// Bytes in address order, `--` for inactive lanes.
+fn lanes(bytes: &[Option<u8>]) -> String {
+    let lanes: Vec<String> = bytes
+        .iter()
+        .map(|byte| byte.map_or("--".to_owned(), |byte| format!("{byte:02x}")))
+        .collect();
+    lanes.join(" ")
+}
+
+impl fmt::Display for MismatchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "compare failed on {} of {} beat(s) of read(0x{:02x}) from 0x{:08x}, the read-back of {}:",
+            self.mismatches.len(),
+            self.beats,
+            self.id,
+            self.addr,
+            self.write
+        )?;
+        let width = self
+            .mismatches
+            .first()
+            .map_or(0, |beat| beat.expected.len() * 3 - 1)
+            .max("expected".len());
+        write!(
+            f,
+            "\n\t{:>4}  {:<10}  {:<30}  {:<width$}  {:<width$}  xor",
+            "beat", "address", "sdram", "expected", "actual"
+        )?;
+        for beat in &self.mismatches {
+            write!(
+                f,
+                "\n\t{:>4}  0x{:08x}  {:<30}  {:<width$}  {:<width$}  {}",
+                beat.beat,
+                beat.addr,
+                beat.sdram.to_string(),
+                lanes(&beat.expected),
+                lanes(&beat.actual),
+                lanes(&beat.xor())
+            )?;
+            for byte in &beat.bytes {
+                write!(
+                    f,
+                    "\n\t      0x{:08x}  {:<30}  ",
+                    byte.addr,
+                    format!("{:#}", byte.sdram)
+                )?;
+                match byte.writer {
+                    Some(writer) => {
+                        write!(f, "expected from {writer}")?;
+                        match writer.write.seq.cmp(&self.write.seq) {
+                            Ordering::Greater => write!(f, ", which overlapped the one read back")?,
+                            // its strobe left the byte alone
+                            Ordering::Less => write!(f, ", not written by the one read back")?,
+                            Ordering::Equal => {}
+                        }
+                    }
+                    None => write!(f, "no provenance")?,
+                }
+                if byte.faulted {
+                    write!(f, ", hit by an injected fault")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
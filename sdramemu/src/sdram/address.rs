@@ -0,0 +1,270 @@
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use super::Geometry;
+use crate::parse_number;
+
+/// A byte of the SDRAM, as the pins address it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct SdramAddress {
+    pub cs: u32,
+    pub bank: u32,
+    pub row: u32,
+    pub col: u32,
+    /// Byte within the DQ word of the column.
+    pub byte: u32,
+}
+
+impl fmt::Display for SdramAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "cs{}/bank{}/row0x{:x}/col0x{:x}",
+            self.cs, self.bank, self.row, self.col
+        )?;
+        if f.alternate() {
+            write!(f, "/byte{}", self.byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse `<cs>/<bank>/<row>/<col>[/<byte>]`, every field optionally prefixed
+/// with its name as `Display` prints it.
+impl FromStr for SdramAddress {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = input.split('/').collect();
+        if !(4..=5).contains(&fields.len()) {
+            return Err(format!(
+                "sdram address `{input}` is not `<cs>/<bank>/<row>/<col>[/<byte>]`"
+            ));
+        }
+        let names = ["cs", "bank", "row", "col", "byte"];
+        let mut values = [0; 5];
+        for ((field, name), value) in fields.iter().zip(names).zip(&mut values) {
+            let number = field.strip_prefix(name).unwrap_or(field);
+            *value = parse_number(number)
+                .ok()
+                .and_then(|number| u32::try_from(number).ok())
+                .ok_or_else(|| format!("invalid {name} `{field}` in sdram address `{input}`"))?;
+        }
+        let [cs, bank, row, col, byte] = values;
+        Ok(Self {
+            cs,
+            bank,
+            row,
+            col,
+            byte,
+        })
+    }
+}
+
+/// How the controller spreads AXI addresses over the SDRAM, see `addr_col_w`,
+/// `addr_bank_w` and `addr_row_w` in the controller. From the least
+/// significant bit up an address holds the byte within a DQ word, the column,
+/// the bank and the row.
+///
+/// The chip select above the row is a convention of the testbench: the
+/// controller does not decode it and drives CS# from its command alone
+/// (`sdram.cs := command_q(3)`). It gives every chip select its own range so
+/// that a write to one the controller never selected shows up. Bits above
+/// are ignored, so the map repeats every `capacity()` bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressMap {
+    geometry: Geometry,
+    cs_width: u32,
+}
+
+impl AddressMap {
+    pub fn new(geometry: Geometry, cs_width: u32) -> Self {
+        Self { geometry, cs_width }
+    }
+
+    pub fn geometry(&self) -> &Geometry {
+        &self.geometry
+    }
+
+    pub fn cs_width(&self) -> u32 {
+        self.cs_width
+    }
+
+    /// Bytes of a DQ word.
+    pub fn word_bytes(&self) -> u64 {
+        self.geometry.data_width as u64 / 8
+    }
+
+    /// Bytes behind one chip select.
+    pub fn cs_bytes(&self) -> u64 {
+        let geometry = &self.geometry;
+        geometry.rows() as u64 * geometry.banks as u64 * geometry.cols() as u64 * self.word_bytes()
+    }
+
+    /// Bytes behind every chip select together.
+    pub fn capacity(&self) -> u64 {
+        self.cs_bytes() * self.cs_width as u64
+    }
+
+    /// Split an AXI address range where it crosses from one chip select to
+    /// the next, along with the chip select of each part.
+    pub fn cs_ranges(&self, range: Range<u64>) -> Vec<(u32, Range<u64>)> {
+        let mut ranges = Vec::new();
+        let mut start = range.start;
+        while start < range.end {
+            let end = (start / self.cs_bytes() + 1) * self.cs_bytes();
+            let end = end.min(range.end);
+            ranges.push((self.decode(start).cs, start..end));
+            start = end;
+        }
+        ranges
+    }
+
+    pub fn decode(&self, addr: u64) -> SdramAddress {
+        let geometry = &self.geometry;
+        let mut rest = addr % self.capacity();
+        let mut field = |size: u32| {
+            let value = (rest % size as u64) as u32;
+            rest /= size as u64;
+            value
+        };
+        let byte = field(self.word_bytes() as u32);
+        let col = field(geometry.cols());
+        let bank = field(geometry.banks);
+        let row = field(geometry.rows());
+        let cs = field(self.cs_width);
+        SdramAddress {
+            cs,
+            bank,
+            row,
+            col,
+            byte,
+        }
+    }
+
+    /// The lowest AXI address of `address`, `None` if a field is out of
+    /// range.
+    pub fn encode(&self, address: &SdramAddress) -> Option<u64> {
+        let geometry = &self.geometry;
+        let fields = [
+            (address.cs, self.cs_width),
+            (address.row, geometry.rows()),
+            (address.bank, geometry.banks),
+            (address.col, geometry.cols()),
+            (address.byte, self.word_bytes() as u32),
+        ];
+        fields.iter().try_fold(0, |addr, &(value, size)| {
+            (value < size).then_some(addr * size as u64 + value as u64)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two chip selects of the default part, 32 MiB each.
+    fn map() -> AddressMap {
+        let geometry = Geometry {
+            row_width: 13,
+            col_width: 9,
+            banks: 4,
+            data_width: 16,
+        };
+        AddressMap::new(geometry, 2)
+    }
+
+    const CS1: SdramAddress = SdramAddress {
+        cs: 1,
+        bank: 1,
+        row: 0x12,
+        col: 0x1a3,
+        byte: 1,
+    };
+
+    #[test]
+    fn decodes_the_fields_in_order() {
+        let map = map();
+        assert_eq!(map.word_bytes(), 2);
+        assert_eq!(map.cs_bytes(), 0x200_0000);
+        assert_eq!(map.capacity(), 0x400_0000);
+        let addr = (1 << 25) | (0x12 << 12) | (1 << 10) | (0x1a3 << 1) | 1;
+        assert_eq!(map.decode(addr), CS1);
+        assert_eq!(map.encode(&CS1), Some(addr));
+        // bits above the chip select are ignored
+        assert_eq!(map.decode(0xfc00_0000 + addr), CS1);
+    }
+
+    #[test]
+    fn round_trips() {
+        let map = map();
+        for addr in (0..map.capacity()).step_by(0x1_2345) {
+            assert_eq!(map.encode(&map.decode(addr)), Some(addr), "0x{addr:x}");
+        }
+        for address in [
+            SdramAddress::default(),
+            CS1,
+            SdramAddress {
+                cs: 1,
+                bank: 3,
+                row: 0x1fff,
+                col: 0x1ff,
+                byte: 1,
+            },
+        ] {
+            assert_eq!(map.decode(map.encode(&address).unwrap()), address);
+        }
+    }
+
+    #[test]
+    fn does_not_encode_fields_out_of_range() {
+        let map = map();
+        for address in [
+            SdramAddress { cs: 2, ..CS1 },
+            SdramAddress { bank: 4, ..CS1 },
+            SdramAddress { row: 0x2000, ..CS1 },
+            SdramAddress { col: 0x200, ..CS1 },
+            SdramAddress { byte: 2, ..CS1 },
+        ] {
+            assert_eq!(map.encode(&address), None, "{address:#}");
+        }
+    }
+
+    #[test]
+    fn splits_ranges_at_chip_selects() {
+        let map = map();
+        let base = 0xfc00_0000;
+        assert_eq!(
+            map.cs_ranges(base..base + map.capacity()),
+            [
+                (0, base..base + 0x200_0000),
+                (1, base + 0x200_0000..base + 0x400_0000),
+            ]
+        );
+        assert_eq!(map.cs_ranges(0x1000..0x2000), [(0, 0x1000..0x2000)]);
+        assert_eq!(
+            map.cs_ranges(0x1ff_f000..0x200_1000),
+            [(0, 0x1ff_f000..0x200_0000), (1, 0x200_0000..0x200_1000)]
+        );
+        // back to chip select 0 where the map repeats
+        assert_eq!(
+            map.cs_ranges(0x3ff_f000..0x400_1000),
+            [(1, 0x3ff_f000..0x400_0000), (0, 0x400_0000..0x400_1000)]
+        );
+    }
+
+    #[test]
+    fn parses_what_it_prints() {
+        assert_eq!(format!("{CS1:#}"), "cs1/bank1/row0x12/col0x1a3/byte1");
+        assert_eq!(format!("{CS1:#}").parse(), Ok(CS1));
+        assert_eq!(
+            "1/1/0x12/0x1a3".parse(),
+            Ok(SdramAddress { byte: 0, ..CS1 })
+        );
+        assert!("cs1/bank1/row0x12".parse::<SdramAddress>().is_err());
+        assert!("cs1/bankx/row0x12/col0".parse::<SdramAddress>().is_err());
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use super::{Command, Geometry, SdramPins};
+
+/// Commands counted, in the order the summary lists them.
+const COMMANDS: [Command; 7] = [
+    Command::Active,
+    Command::Read,
+    Command::Write,
+    Command::Precharge,
+    Command::BurstTerminate,
+    Command::Refresh,
+    Command::LoadMode,
+];
+
+/// What the controller exercised on one chip select, clocked once per rising
+/// edge like `SdramDevice`. A chip select never sent an ACTIVE was not
+/// verified at all, whatever else passed.
+pub struct Coverage {
+    geometry: Geometry,
+    /// Rising edges seen, 0 when the pins are not simulated.
+    clocks: u64,
+    commands: [u64; COMMANDS.len()],
+    /// READ and WRITE commands with auto precharge.
+    auto_precharge: u64,
+    /// Bank and row of every ACTIVE.
+    rows: HashSet<(u32, u32)>,
+}
+
+impl Coverage {
+    pub fn new(geometry: Geometry) -> Self {
+        Self {
+            geometry,
+            clocks: 0,
+            commands: [0; COMMANDS.len()],
+            auto_precharge: 0,
+            rows: HashSet::new(),
+        }
+    }
+
+    pub fn tick(&mut self, pins: &SdramPins) {
+        self.clocks += 1;
+        if !pins.cke {
+            return;
+        }
+        let command = pins.command();
+        let Some(index) = COMMANDS.iter().position(|&other| other == command) else {
+            return;
+        };
+        self.commands[index] += 1;
+        match command {
+            Command::Active => {
+                let bank = pins.bs as u32 % self.geometry.banks;
+                let row = pins.addr as u32 & (self.geometry.rows() - 1);
+                self.rows.insert((bank, row));
+            }
+            Command::Read | Command::Write if pins.a10() => self.auto_precharge += 1,
+            _ => {}
+        }
+    }
+
+    pub fn clocks(&self) -> u64 {
+        self.clocks
+    }
+
+    /// Times `command` was issued, DESELECT and NOP are not counted.
+    pub fn count(&self, command: Command) -> u64 {
+        COMMANDS
+            .iter()
+            .position(|&other| other == command)
+            .map_or(0, |index| self.commands[index])
+    }
+
+    /// Distinct rows opened in each bank.
+    pub fn rows_per_bank(&self) -> Vec<usize> {
+        (0..self.geometry.banks)
+            .map(|bank| self.rows.iter().filter(|(other, _)| *other == bank).count())
+            .collect()
+    }
+}
+
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let commands: Vec<String> = COMMANDS
+            .iter()
+            .zip(self.commands)
+            .map(|(command, count)| format!("{command:?} {count}"))
+            .collect();
+        write!(
+            f,
+            "commands: {} ({} with auto precharge)",
+            commands.join(", "),
+            self.auto_precharge
+        )?;
+        let rows: Vec<String> = self
+            .rows_per_bank()
+            .iter()
+            .enumerate()
+            .map(|(bank, rows)| format!("bank{bank} {rows}"))
+            .collect();
+        write!(
+            f,
+            "\n\trows opened, of {} per bank: {}",
+            self.geometry.rows(),
+            rows.join(", ")
+        )
+    }
+}
@@ -0,0 +1,276 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+
+use super::{
+    BurstType, Command, FaultModel, Geometry, Injection, ModeRegister, ReservedMode, SdramPins,
+};
+
+/// A command the device cannot execute in its current state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceError {
+    /// READ or WRITE to a bank without an open row.
+    BankIdle { command: Command, bank: u32 },
+    /// ACTIVE to a bank that already has a row open.
+    BankActive { bank: u32, row: u32 },
+    /// REFRESH or LOAD MODE while a bank has a row open.
+    NotIdle { command: Command, bank: u32 },
+    /// READ or WRITE before the mode register was ever loaded.
+    ModeUnset { command: Command },
+    /// LOAD MODE with an encoding the device does not support.
+    ReservedMode(ReservedMode),
+}
+
+impl fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BankIdle { command, bank } => {
+                write!(f, "{command:?} to bank {bank} which has no open row")
+            }
+            Self::BankActive { bank, row } => {
+                write!(f, "ACTIVE to bank {bank} which has row 0x{row:x} open")
+            }
+            Self::NotIdle { command, bank } => {
+                write!(f, "{command:?} while bank {bank} has a row open")
+            }
+            Self::ModeUnset { command } => {
+                write!(f, "{command:?} before the mode register was loaded")
+            }
+            Self::ReservedMode(reserved) => write!(f, "{reserved}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Burst {
+    write: bool,
+    bank: u32,
+    row: u32,
+    col: u32,
+    beat: u32,
+    length: u32,
+    auto_precharge: bool,
+}
+
+/// Behavioural model of one SDR SDRAM device, clocked once per rising edge.
+///
+/// Only the command semantics are modelled: timing is left to the checkers,
+/// so a command issued too early still takes effect.
+pub struct SdramDevice {
+    geometry: Geometry,
+    mode: Option<ModeRegister>,
+    open_rows: Vec<Option<u32>>,
+    burst: Option<Burst>,
+    /// Read data and the tick to drive it at, in tick order.
+    read_data: VecDeque<(u64, u32)>,
+    /// DQM of the previous edge. Read data is masked two edges after DQM,
+    /// i.e. as it is driven after the next one.
+    last_dqm: u8,
+    tick: u64,
+    cells: BTreeMap<u64, u32>,
+    faults: Option<FaultModel>,
+}
+
+impl SdramDevice {
+    pub fn new(geometry: Geometry) -> Self {
+        Self {
+            geometry,
+            mode: None,
+            open_rows: vec![None; geometry.banks as usize],
+            burst: None,
+            read_data: VecDeque::new(),
+            last_dqm: 0,
+            tick: 0,
+            cells: BTreeMap::new(),
+            faults: None,
+        }
+    }
+
+    /// Corrupt the content of the device as `faults` describe.
+    pub fn inject(&mut self, faults: FaultModel) {
+        self.faults = Some(faults);
+    }
+
+    pub fn faults(&self) -> Option<&FaultModel> {
+        self.faults.as_ref()
+    }
+
+    /// The corruption faults injected since the last call.
+    pub fn take_injections(&mut self) -> Vec<Injection> {
+        self.faults
+            .as_mut()
+            .map_or_else(Vec::new, FaultModel::take_injections)
+    }
+
+    /// Let every row whose retention deadline passed decay, as the content
+    /// of the array is only updated when a row is refreshed otherwise.
+    pub fn expire(&mut self) {
+        if let Some(faults) = &mut self.faults {
+            faults.expire(self.tick, &mut self.cells);
+        }
+    }
+
+    pub fn geometry(&self) -> &Geometry {
+        &self.geometry
+    }
+
+    /// Content of a cell, unwritten cells read as zero.
+    pub fn peek(&self, bank: u32, row: u32, col: u32) -> u32 {
+        self.load(self.geometry.cell_index(bank, row, col))
+    }
+
+    fn load(&self, index: u64) -> u32 {
+        let value = self.cells.get(&index).copied().unwrap_or(0);
+        self.faults
+            .as_ref()
+            .map_or(value, |faults| faults.load(index, value))
+    }
+
+    fn data_mask(&self) -> u32 {
+        u32::MAX >> (u32::BITS - self.geometry.data_width)
+    }
+
+    /// Bits of DQ masked by `dqm`, one DQM bit per byte.
+    fn dqm_mask(&self, dqm: u8) -> u32 {
+        (0..self.geometry.data_width.div_ceil(8))
+            .filter(|byte| (dqm >> byte) & 1 != 0)
+            .fold(0, |mask, byte| mask | 0xff << (byte * 8))
+            & self.data_mask()
+    }
+
+    /// Apply the pins of a rising edge, returning the data the device drives
+    /// on DQ until the next one. A command that cannot execute is dropped.
+    pub fn tick(&mut self, pins: &SdramPins) -> Result<u32, DeviceError> {
+        if let Some(faults) = &mut self.faults {
+            faults.tick(self.tick, &mut self.cells);
+        }
+        let result = if pins.cke { self.execute(pins) } else { Ok(()) };
+        if pins.cke {
+            self.advance_burst(pins);
+        }
+
+        let mut dq = 0;
+        while let Some(&(tick, data)) = self.read_data.front() {
+            if tick > self.tick {
+                break;
+            }
+            self.read_data.pop_front();
+            if tick == self.tick {
+                dq = data & !self.dqm_mask(self.last_dqm);
+            }
+        }
+        self.last_dqm = pins.dqm;
+        self.tick += 1;
+        result.map(|_| dq)
+    }
+
+    fn execute(&mut self, pins: &SdramPins) -> Result<(), DeviceError> {
+        let command = pins.command();
+        let bank = pins.bs as u32 % self.geometry.banks;
+        match command {
+            Command::Deselect | Command::Nop => {}
+            Command::BurstTerminate => self.burst = None,
+            Command::Read | Command::Write => {
+                self.burst = None;
+                let mode = self.mode.ok_or(DeviceError::ModeUnset { command })?;
+                let row =
+                    self.open_rows[bank as usize].ok_or(DeviceError::BankIdle { command, bank })?;
+                let write = command == Command::Write;
+                self.burst = Some(Burst {
+                    write,
+                    bank,
+                    row,
+                    col: pins.addr as u32 & (self.geometry.cols() - 1),
+                    beat: 0,
+                    length: if write {
+                        mode.write_beats(&self.geometry)
+                    } else {
+                        mode.read_beats(&self.geometry)
+                    },
+                    auto_precharge: pins.a10(),
+                });
+            }
+            Command::Active => {
+                if let Some(row) = self.open_rows[bank as usize] {
+                    return Err(DeviceError::BankActive { bank, row });
+                }
+                let row = pins.addr as u32 & (self.geometry.rows() - 1);
+                if let Some(faults) = &mut self.faults {
+                    faults.activate(self.tick, &mut self.cells, bank, row);
+                }
+                self.open_rows[bank as usize] = Some(row);
+            }
+            Command::Precharge => {
+                if pins.a10() {
+                    self.open_rows.fill(None);
+                } else {
+                    self.open_rows[bank as usize] = None;
+                }
+                if self
+                    .burst
+                    .is_some_and(|burst| pins.a10() || burst.bank == bank)
+                {
+                    self.burst = None;
+                }
+            }
+            Command::Refresh | Command::LoadMode => {
+                self.check_idle(command)?;
+                if let (Command::Refresh, Some(faults)) = (command, &mut self.faults) {
+                    faults.refresh(self.tick, &mut self.cells);
+                }
+                if command == Command::LoadMode {
+                    self.mode =
+                        Some(ModeRegister::decode(pins.addr).map_err(DeviceError::ReservedMode)?);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check_idle(&self, command: Command) -> Result<(), DeviceError> {
+        match self.open_rows.iter().position(Option::is_some) {
+            Some(bank) => Err(DeviceError::NotIdle {
+                command,
+                bank: bank as u32,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Transfer the current beat of the burst in progress.
+    fn advance_burst(&mut self, pins: &SdramPins) {
+        let (Some(mut burst), Some(mode)) = (self.burst, self.mode) else {
+            return;
+        };
+        // the burst wraps within a block of its length
+        let offset = if mode.burst_type == BurstType::Interleaved {
+            burst.col ^ burst.beat
+        } else {
+            burst.col + burst.beat
+        } % burst.length;
+        let col = ((burst.col & !(burst.length - 1)) | offset) & (self.geometry.cols() - 1);
+        let index = self.geometry.cell_index(burst.bank, burst.row, col);
+        if burst.write {
+            let mask = self.data_mask() & !self.dqm_mask(pins.dqm);
+            let cell = self.cells.get(&index).copied().unwrap_or(0);
+            let mut value = (cell & !mask) | (pins.dq & mask);
+            if let Some(faults) = &mut self.faults {
+                value = faults.store(index, value, mask);
+            }
+            self.cells.insert(index, value);
+        } else {
+            let data = self.load(index);
+            self.read_data
+                .push_back((self.tick + mode.cas_latency as u64 - 1, data));
+        }
+
+        burst.beat += 1;
+        self.burst = if burst.beat < burst.length {
+            Some(burst)
+        } else {
+            if burst.auto_precharge {
+                self.open_rows[burst.bank as usize] = None;
+            }
+            None
+        };
+    }
+}
@@ -0,0 +1,692 @@
+//! Faults injected into the device model, given with `+sdram-faults=<path>`,
+//! one per line, or inline with `+sdram-fault=<fault>[,<fault>...]`:
+//!
+//! ```text
+//! # <cell> is <cs>/<bank>/<row>/<col>[/<byte>] as `sdram-addr` prints it,
+//! # <bit> counts from the byte if given, else from the DQ word
+//! stuck-at-0:<cell>:<bit>
+//! stuck-at-1:<cell>:<bit>
+//! # invert a bit once, at a clock of the chip select counted from power-up
+//! flip:<cell>:<bit>:<clock>
+//! # invert a random bit of a random written cell, with a probability per clock
+//! flip-rate:<cs>:<probability>
+//! # rows not refreshed, by REFRESH or ACTIVE, within a deadline in ns decay to 0
+//! retention:<cs>:<ns>
+//! ```
+
+use anyhow::{bail, ensure, Context, Result};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use super::{Geometry, SdramAddress};
+use crate::parse_number;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fault {
+    /// A bit of a cell that always holds `value`.
+    StuckAt {
+        cell: SdramAddress,
+        bit: u32,
+        value: bool,
+    },
+    /// A bit of a cell inverted once.
+    BitFlip {
+        cell: SdramAddress,
+        bit: u32,
+        clock: u64,
+    },
+    /// A random bit of a random written cell inverted with probability `rate`
+    /// every clock.
+    RandomFlips { cs: u32, rate: f64 },
+    /// Rows losing their content when not refreshed within `deadline` ns.
+    Retention { cs: u32, deadline: u64 },
+}
+
+impl Fault {
+    pub fn cs(&self) -> u32 {
+        match self {
+            Self::StuckAt { cell, .. } | Self::BitFlip { cell, .. } => cell.cs,
+            Self::RandomFlips { cs, .. } | Self::Retention { cs, .. } => *cs,
+        }
+    }
+
+    /// Parse `<fault>[,<fault>...]`.
+    pub fn parse_list(input: &str) -> Result<Vec<Self>> {
+        input.split(',').map(|fault| fault.trim().parse()).collect()
+    }
+
+    /// Read a fault file, one fault per line, `#` starting a comment.
+    pub fn load(path: impl AsRef<Path>) -> Result<Vec<Self>> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read sdram faults `{}`", path.display()))?;
+        content
+            .lines()
+            .enumerate()
+            .filter_map(|(index, line)| {
+                let line = line.split('#').next().unwrap().trim();
+                (!line.is_empty()).then(|| {
+                    line.parse().with_context(|| {
+                        format!("invalid sdram fault at {}:{}", path.display(), index + 1)
+                    })
+                })
+            })
+            .collect()
+    }
+}
+
+fn parse_field<T: TryFrom<u64>>(name: &str, field: &str) -> Result<T> {
+    parse_number(field)
+        .ok()
+        .and_then(|value| T::try_from(value).ok())
+        .with_context(|| format!("invalid {name} `{field}`"))
+}
+
+/// The bit within the DQ word, counting from the byte of `cell`.
+fn parse_bit(cell: &mut SdramAddress, field: &str) -> Result<u32> {
+    let bit = parse_field::<u32>("bit", field)? + cell.byte * 8;
+    cell.byte = 0;
+    Ok(bit)
+}
+
+impl FromStr for Fault {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        let fields: Vec<&str> = input.split(':').collect();
+        let cell =
+            |field: &str| -> Result<SdramAddress> { field.parse().map_err(anyhow::Error::msg) };
+        let cs =
+            |field: &str| parse_field("chip select", field.strip_prefix("cs").unwrap_or(field));
+        let fault = match fields[..] {
+            [kind @ ("stuck-at-0" | "stuck-at-1"), location, bit] => {
+                let mut cell = cell(location)?;
+                Self::StuckAt {
+                    bit: parse_bit(&mut cell, bit)?,
+                    cell,
+                    value: kind == "stuck-at-1",
+                }
+            }
+            ["flip", location, bit, clock] => {
+                let mut cell = cell(location)?;
+                Self::BitFlip {
+                    bit: parse_bit(&mut cell, bit)?,
+                    cell,
+                    clock: parse_field("clock", clock)?,
+                }
+            }
+            ["flip-rate", chip_select, rate] => {
+                let rate: f64 = rate
+                    .parse()
+                    .ok()
+                    .filter(|rate| (0.0..=1.0).contains(rate))
+                    .with_context(|| format!("flip rate `{rate}` is not a probability"))?;
+                Self::RandomFlips {
+                    cs: cs(chip_select)?,
+                    rate,
+                }
+            }
+            ["retention", chip_select, deadline] => Self::Retention {
+                cs: cs(chip_select)?,
+                deadline: parse_field("retention deadline", deadline)?,
+            },
+            _ => bail!(
+                "sdram fault `{input}` is not one of `stuck-at-0:<cell>:<bit>`, `stuck-at-1:<cell>:<bit>`, \
+                 `flip:<cell>:<bit>:<clock>`, `flip-rate:<cs>:<probability>` or `retention:<cs>:<ns>`"
+            ),
+        };
+        Ok(fault)
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::StuckAt { cell, bit, value } => {
+                write!(f, "bit {bit} of {cell} stuck at {}", *value as u8)
+            }
+            Self::BitFlip { cell, bit, clock } => {
+                write!(f, "bit {bit} of {cell} flipped at clock {clock}")
+            }
+            Self::RandomFlips { cs, rate } => {
+                write!(f, "random bit flips in cs{cs}, {rate} per clock")
+            }
+            Self::Retention { cs, deadline } => {
+                write!(
+                    f,
+                    "rows of cs{cs} decay {deadline}ns after their last refresh"
+                )
+            }
+        }
+    }
+}
+
+/// Corruption a fault caused, for the log to tell it from a bug.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Injection {
+    /// Stuck bits overrode a write.
+    StuckAt {
+        bank: u32,
+        row: u32,
+        col: u32,
+        written: u32,
+        stored: u32,
+    },
+    BitFlip {
+        bank: u32,
+        row: u32,
+        col: u32,
+        bit: u32,
+        random: bool,
+    },
+    /// `cells` cells of a row lost their content.
+    Retention {
+        bank: u32,
+        row: u32,
+        age: u64,
+        cells: usize,
+    },
+}
+
+impl fmt::Display for Injection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::StuckAt {
+                bank,
+                row,
+                col,
+                written,
+                stored,
+            } => write!(
+                f,
+                "stuck bits stored 0x{stored:x} for 0x{written:x} written to bank{bank}/row0x{row:x}/col0x{col:x}"
+            ),
+            Self::BitFlip {
+                bank,
+                row,
+                col,
+                bit,
+                random,
+            } => write!(
+                f,
+                "{}bit {bit} of bank{bank}/row0x{row:x}/col0x{col:x} flipped",
+                if *random { "random " } else { "" }
+            ),
+            Self::Retention {
+                bank,
+                row,
+                age,
+                cells,
+            } => write!(
+                f,
+                "{cells} cell(s) of bank{bank}/row0x{row:x} decayed, {age} clock(s) without refresh"
+            ),
+        }
+    }
+}
+
+/// The faults of one device and what they did so far. The device model
+/// calls into it as cells are stored, loaded and refreshed.
+pub struct FaultModel {
+    geometry: Geometry,
+    /// Mask and value of the stuck bits of each cell.
+    stuck: HashMap<u64, (u32, u32)>,
+    /// Clock, cell and bit of the flips to come, in clock order.
+    flips: VecDeque<(u64, u64, u32)>,
+    flip_rate: f64,
+    rng: StdRng,
+    /// Retention deadline in clocks.
+    deadline: Option<u64>,
+    /// Clock each row of each bank was last refreshed at.
+    refreshed: Vec<u64>,
+    /// Row the next REFRESH refreshes in every bank.
+    refresh_row: u32,
+    /// Bits of each cell a fault changed or holds, by cell index.
+    faulted: HashMap<u64, u32>,
+    injections: Vec<Injection>,
+}
+
+impl FaultModel {
+    /// The faults of one chip select, retention deadlines converted to clocks
+    /// at `mhz`. Random flips draw from `seed`.
+    pub fn new<'a>(
+        geometry: Geometry,
+        faults: impl IntoIterator<Item = &'a Fault>,
+        mhz: u64,
+        seed: u64,
+    ) -> Result<Self> {
+        let mut model = Self {
+            geometry,
+            stuck: HashMap::new(),
+            flips: VecDeque::new(),
+            flip_rate: 0.0,
+            rng: StdRng::seed_from_u64(seed),
+            deadline: None,
+            refreshed: Vec::new(),
+            refresh_row: 0,
+            faulted: HashMap::new(),
+            injections: Vec::new(),
+        };
+        let mut flips = Vec::new();
+        for fault in faults {
+            match *fault {
+                Fault::StuckAt { cell, bit, value } => {
+                    let index = model.index(&cell, bit).with_context(|| fault.to_string())?;
+                    let (mask, stuck) = model.stuck.entry(index).or_default();
+                    *mask |= 1 << bit;
+                    *stuck = (*stuck & !(1 << bit)) | (value as u32) << bit;
+                    *model.faulted.entry(index).or_default() |= 1 << bit;
+                }
+                Fault::BitFlip { cell, bit, clock } => {
+                    let index = model.index(&cell, bit).with_context(|| fault.to_string())?;
+                    flips.push((clock, index, bit));
+                }
+                Fault::RandomFlips { rate, .. } => model.flip_rate += rate,
+                Fault::Retention { deadline, .. } => {
+                    ensure!(deadline > 0, "{fault}: the deadline must not be zero");
+                    let clocks = (deadline * mhz).div_ceil(1000);
+                    model.deadline = Some(model.deadline.map_or(clocks, |other| other.min(clocks)));
+                }
+            }
+        }
+        flips.sort_by_key(|&(clock, ..)| clock);
+        model.flips = flips.into();
+        model.flip_rate = model.flip_rate.min(1.0);
+        if model.deadline.is_some() {
+            model.refreshed = vec![0; (geometry.banks * geometry.rows()) as usize];
+        }
+        Ok(model)
+    }
+
+    fn index(&self, cell: &SdramAddress, bit: u32) -> Result<u64> {
+        let geometry = &self.geometry;
+        ensure!(
+            cell.bank < geometry.banks && cell.row < geometry.rows() && cell.col < geometry.cols(),
+            "{cell} is outside the device"
+        );
+        ensure!(
+            bit < geometry.data_width,
+            "bit {bit} is outside the {}-bit DQ word",
+            geometry.data_width
+        );
+        Ok(geometry.cell_index(cell.bank, cell.row, cell.col))
+    }
+
+    /// The value a cell holds when `value` is written to the bits in `mask`.
+    pub(super) fn store(&mut self, index: u64, value: u32, mask: u32) -> u32 {
+        let stored = self.load(index, value);
+        if (stored ^ value) & mask != 0 {
+            let (bank, row, col) = self.geometry.cell_location(index);
+            self.injections.push(Injection::StuckAt {
+                bank,
+                row,
+                col,
+                written: value & mask,
+                stored: stored & mask,
+            });
+        }
+        stored
+    }
+
+    /// What a cell holding `value` reads as.
+    pub(super) fn load(&self, index: u64, value: u32) -> u32 {
+        match self.stuck.get(&index) {
+            Some(&(mask, stuck)) => (value & !mask) | stuck,
+            None => value,
+        }
+    }
+
+    /// Inject the flips due at `clock`.
+    pub(super) fn tick(&mut self, clock: u64, cells: &mut BTreeMap<u64, u32>) {
+        while let Some(&(at, index, bit)) = self.flips.front() {
+            if at > clock {
+                break;
+            }
+            self.flips.pop_front();
+            self.flip(cells, index, bit, false);
+        }
+        if self.flip_rate > 0.0 && !cells.is_empty() && self.rng.gen_bool(self.flip_rate) {
+            // the first written cell from a random one, which keeps the pick
+            // reproducible
+            let last = *cells.keys().next_back().unwrap();
+            let start = self.rng.gen_range(0..=last);
+            let index = *cells.range(start..).next().unwrap().0;
+            let bit = self.rng.gen_range(0..self.geometry.data_width);
+            self.flip(cells, index, bit, true);
+        }
+    }
+
+    fn flip(&mut self, cells: &mut BTreeMap<u64, u32>, index: u64, bit: u32, random: bool) {
+        *cells.entry(index).or_insert(0) ^= 1 << bit;
+        *self.faulted.entry(index).or_default() |= 1 << bit;
+        let (bank, row, col) = self.geometry.cell_location(index);
+        self.injections.push(Injection::BitFlip {
+            bank,
+            row,
+            col,
+            bit,
+            random,
+        });
+    }
+
+    /// A REFRESH at `clock`, refreshing the next row of every bank.
+    pub(super) fn refresh(&mut self, clock: u64, cells: &mut BTreeMap<u64, u32>) {
+        if self.deadline.is_none() {
+            return;
+        }
+        for bank in 0..self.geometry.banks {
+            self.restore(clock, cells, bank, self.refresh_row);
+        }
+        self.refresh_row = (self.refresh_row + 1) % self.geometry.rows();
+    }
+
+    /// An ACTIVE at `clock`, which refreshes the row it opens.
+    pub(super) fn activate(
+        &mut self,
+        clock: u64,
+        cells: &mut BTreeMap<u64, u32>,
+        bank: u32,
+        row: u32,
+    ) {
+        if self.deadline.is_some() {
+            self.restore(clock, cells, bank, row);
+        }
+    }
+
+    /// Decay every row past the deadline at `clock`, without refreshing any.
+    pub(super) fn expire(&mut self, clock: u64, cells: &mut BTreeMap<u64, u32>) {
+        if self.deadline.is_none() {
+            return;
+        }
+        for bank in 0..self.geometry.banks {
+            for row in 0..self.geometry.rows() {
+                let last = self.refreshed[(bank * self.geometry.rows() + row) as usize];
+                self.decay(clock, last, cells, bank, row);
+            }
+        }
+    }
+
+    /// Refresh a row at `clock`, after it decayed if its deadline passed.
+    fn restore(&mut self, clock: u64, cells: &mut BTreeMap<u64, u32>, bank: u32, row: u32) {
+        let slot = (bank * self.geometry.rows() + row) as usize;
+        let last = std::mem::replace(&mut self.refreshed[slot], clock);
+        self.decay(clock, last, cells, bank, row);
+    }
+
+    fn decay(
+        &mut self,
+        clock: u64,
+        last: u64,
+        cells: &mut BTreeMap<u64, u32>,
+        bank: u32,
+        row: u32,
+    ) {
+        let age = clock - last;
+        if self.deadline.map_or(true, |deadline| age <= deadline) {
+            return;
+        }
+        let first = self.geometry.cell_index(bank, row, 0);
+        let mut decayed = 0;
+        for (&index, cell) in cells.range_mut(first..first + self.geometry.cols() as u64) {
+            if *cell != 0 {
+                *self.faulted.entry(index).or_default() |= *cell;
+                *cell = 0;
+                decayed += 1;
+            }
+        }
+        if decayed > 0 {
+            self.injections.push(Injection::Retention {
+                bank,
+                row,
+                age,
+                cells: decayed,
+            });
+        }
+    }
+
+    /// Bits of a cell a fault may have changed the content of.
+    pub fn faulted(&self, bank: u32, row: u32, col: u32) -> u32 {
+        self.faulted
+            .get(&self.geometry.cell_index(bank, row, col))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// The corruption injected since the last call.
+    pub fn take_injections(&mut self) -> Vec<Injection> {
+        std::mem::take(&mut self.injections)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2 banks of 4 rows of 4 columns.
+    const GEOMETRY: Geometry = Geometry {
+        row_width: 2,
+        col_width: 2,
+        banks: 2,
+        data_width: 16,
+    };
+
+    fn cell(bank: u32, row: u32, col: u32) -> SdramAddress {
+        SdramAddress {
+            cs: 0,
+            bank,
+            row,
+            col,
+            byte: 0,
+        }
+    }
+
+    fn model(faults: &[Fault]) -> Result<FaultModel> {
+        FaultModel::new(GEOMETRY, faults, 1000, 1)
+    }
+
+    #[test]
+    fn parses_each_fault_kind() {
+        assert_eq!(
+            "stuck-at-1:cs0/bank1/row2/col3:4".parse::<Fault>().unwrap(),
+            Fault::StuckAt {
+                cell: cell(1, 2, 3),
+                bit: 4,
+                value: true,
+            }
+        );
+        // the bit counts from the byte
+        assert_eq!(
+            "stuck-at-0:0/1/2/3/1:2".parse::<Fault>().unwrap(),
+            Fault::StuckAt {
+                cell: cell(1, 2, 3),
+                bit: 10,
+                value: false,
+            }
+        );
+        let flip: Fault = "flip:cs1/bank0/row0x1/col0:15:100".parse().unwrap();
+        assert_eq!(
+            flip,
+            Fault::BitFlip {
+                cell: SdramAddress {
+                    cs: 1,
+                    ..cell(0, 1, 0)
+                },
+                bit: 15,
+                clock: 100,
+            }
+        );
+        assert_eq!(flip.cs(), 1);
+        assert_eq!(
+            Fault::parse_list("flip-rate:cs1:0.5, retention:2:1000").unwrap(),
+            [
+                Fault::RandomFlips { cs: 1, rate: 0.5 },
+                Fault::Retention {
+                    cs: 2,
+                    deadline: 1000,
+                },
+            ]
+        );
+        for invalid in [
+            "flip-rate:0:1.5",
+            "stuck-at-2:0/0/0/0:1",
+            "stuck-at-0:0/0/0:1",
+            "flip:0/0/0/0:1",
+            "retention:cs:10",
+            "retention:0:-1",
+        ] {
+            assert!(invalid.parse::<Fault>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn loads_a_fault_file() {
+        let path = std::env::temp_dir().join(format!("sdramemu-faults-{}.txt", std::process::id()));
+        std::fs::write(
+            &path,
+            "# stuck bits\nstuck-at-1:0/0/0/0:1  # lane 0\n\nretention:0:64000000\n",
+        )
+        .unwrap();
+        assert_eq!(Fault::load(&path).unwrap().len(), 2);
+        std::fs::write(&path, "stuck-at-1:0/0/0/0:1\nflip\n").unwrap();
+        let err = Fault::load(&path).unwrap_err();
+        assert!(format!("{err:#}").contains(":2"), "{err:#}");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rejects_faults_outside_the_device() {
+        let stuck = |cell, bit| Fault::StuckAt {
+            cell,
+            bit,
+            value: true,
+        };
+        assert!(model(&[stuck(cell(0, 4, 0), 0)]).is_err());
+        assert!(model(&[stuck(cell(0, 0, 4), 0)]).is_err());
+        assert!(model(&[stuck(cell(0, 0, 0), 16)]).is_err());
+        assert!(model(&[Fault::Retention { cs: 0, deadline: 0 }]).is_err());
+    }
+
+    #[test]
+    fn holds_stuck_bits() {
+        let mut model = model(&[
+            Fault::StuckAt {
+                cell: cell(1, 2, 3),
+                bit: 4,
+                value: true,
+            },
+            Fault::StuckAt {
+                cell: cell(1, 2, 3),
+                bit: 0,
+                value: false,
+            },
+        ])
+        .unwrap();
+        let index = GEOMETRY.cell_index(1, 2, 3);
+        assert_eq!(model.store(index, 0x0010, 0xffff), 0x0010);
+        assert_eq!(model.take_injections(), []);
+        assert_eq!(model.store(index, 0x0101, 0xffff), 0x0110);
+        assert_eq!(
+            model.take_injections(),
+            [Injection::StuckAt {
+                bank: 1,
+                row: 2,
+                col: 3,
+                written: 0x0101,
+                stored: 0x0110,
+            }]
+        );
+        // bits DQM masks are not written, the byte holding the stuck ones
+        assert_eq!(model.store(index, 0x0100, 0xff00), 0x0110);
+        assert_eq!(model.take_injections(), []);
+        assert_eq!(model.load(index, 0xffff), 0xfffe);
+        assert_eq!(model.faulted(1, 2, 3), 0x0011);
+        assert_eq!(model.faulted(1, 2, 2), 0);
+    }
+
+    #[test]
+    fn flips_bits_at_their_clock() {
+        let mut model = model(&[Fault::BitFlip {
+            cell: cell(0, 1, 1),
+            bit: 3,
+            clock: 5,
+        }])
+        .unwrap();
+        let index = GEOMETRY.cell_index(0, 1, 1);
+        let mut cells = BTreeMap::from([(index, 0x00ff)]);
+        for clock in 0..5 {
+            model.tick(clock, &mut cells);
+        }
+        assert_eq!(model.faulted(0, 1, 1), 0);
+        model.tick(5, &mut cells);
+        assert_eq!(cells[&index], 0x00f7);
+        assert_eq!(model.faulted(0, 1, 1), 0x0008);
+        assert_eq!(
+            model.take_injections(),
+            [Injection::BitFlip {
+                bank: 0,
+                row: 1,
+                col: 1,
+                bit: 3,
+                random: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn flips_random_bits_of_written_cells() {
+        let mut model = model(&[Fault::RandomFlips { cs: 0, rate: 1.0 }]).unwrap();
+        let mut cells = BTreeMap::new();
+        // nothing to flip before anything is written
+        model.tick(0, &mut cells);
+        assert_eq!(model.take_injections(), []);
+        let index = GEOMETRY.cell_index(1, 3, 2);
+        cells.insert(index, 0);
+        model.tick(1, &mut cells);
+        assert_eq!(cells[&index].count_ones(), 1);
+        assert_eq!(model.faulted(1, 3, 2), cells[&index]);
+        assert!(matches!(
+            model.take_injections()[..],
+            [Injection::BitFlip {
+                bank: 1,
+                row: 3,
+                col: 2,
+                random: true,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn decays_rows_not_refreshed_in_time() {
+        // 10 clocks at 1000 MHz
+        let mut model = model(&[Fault::Retention {
+            cs: 0,
+            deadline: 10,
+        }])
+        .unwrap();
+        let (decaying, kept) = (GEOMETRY.cell_index(0, 0, 1), GEOMETRY.cell_index(1, 0, 0));
+        let mut cells = BTreeMap::from([(decaying, 0xabcd), (kept, 0x0001)]);
+        // row 0 of every bank
+        model.refresh(5, &mut cells);
+        // opening a row refreshes it too
+        model.activate(12, &mut cells, 1, 0);
+        model.expire(20, &mut cells);
+        assert_eq!(cells[&decaying], 0);
+        assert_eq!(cells[&kept], 0x0001);
+        assert_eq!(model.faulted(0, 0, 1), 0xabcd);
+        assert_eq!(model.faulted(1, 0, 0), 0);
+        assert_eq!(
+            model.take_injections(),
+            [Injection::Retention {
+                bank: 0,
+                row: 0,
+                age: 15,
+                cells: 1,
+            }]
+        );
+    }
+}
@@ -0,0 +1,294 @@
+use std::fmt;
+
+use super::{Command, SdramPins, Timing};
+
+/// REFRESH commands the power-up sequence needs before LOAD MODE.
+const INIT_REFRESHES: u32 = 2;
+
+/// A step of the power-up sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InitStep {
+    PrechargeAll,
+    /// The REFRESH commands, of which `seen` were issued so far.
+    Refresh {
+        seen: u32,
+    },
+    LoadMode,
+}
+
+impl fmt::Display for InitStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PrechargeAll => write!(f, "PRECHARGE ALL"),
+            Self::Refresh { seen } => {
+                write!(f, "{INIT_REFRESHES} REFRESH commands ({seen} issued)")
+            }
+            Self::LoadMode => write!(f, "LOAD MODE"),
+        }
+    }
+}
+
+/// A command out of place in the power-up sequence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InitViolation {
+    /// A command while the power-up wait was not over.
+    Early {
+        command: Command,
+        elapsed: u64,
+        required: u64,
+    },
+    /// A command issued before a step it has to follow.
+    Skipped { command: Command, step: InitStep },
+}
+
+impl fmt::Display for InitViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Early {
+                command,
+                elapsed,
+                required,
+            } => write!(
+                f,
+                "init: {command:?} {elapsed} clock(s) after power-up, before the {required} clock(s) wait was over"
+            ),
+            Self::Skipped { command, step } => {
+                write!(f, "init: {command:?} before {step}")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    PowerUp,
+    Refreshing(u32),
+    /// Initialised, or given up on after a violation.
+    Done,
+}
+
+/// Checks one chip select goes through the JEDEC power-up sequence before
+/// its first ACTIVE: the tINIT wait, PRECHARGE ALL, two REFRESH commands and
+/// LOAD MODE, in this order. Clocked once per rising edge like `SdramDevice`,
+/// counting from the first one.
+///
+/// Only the order is checked here, the intervals between the steps are up
+/// to `TimingChecker`.
+pub struct InitChecker {
+    t_init: u64,
+    clock: u64,
+    stage: Stage,
+}
+
+impl InitChecker {
+    pub fn new(timing: &Timing, mhz: u64) -> Self {
+        Self {
+            t_init: (timing.t_init * mhz).div_ceil(1000),
+            clock: 0,
+            stage: Stage::PowerUp,
+        }
+    }
+
+    /// Check the command sampled at a rising edge. Only the first violation
+    /// is reported, the rest of the sequence is not checked after it.
+    pub fn tick(&mut self, pins: &SdramPins) -> Option<InitViolation> {
+        let clock = self.clock;
+        self.clock += 1;
+        let command = pins.command();
+        if !pins.cke
+            || self.stage == Stage::Done
+            || matches!(command, Command::Deselect | Command::Nop)
+        {
+            return None;
+        }
+
+        let violation = if clock < self.t_init {
+            Some(InitViolation::Early {
+                command,
+                elapsed: clock,
+                required: self.t_init,
+            })
+        } else {
+            self.advance(pins)
+        };
+        if violation.is_some() {
+            self.stage = Stage::Done;
+        }
+        violation
+    }
+
+    fn advance(&mut self, pins: &SdramPins) -> Option<InitViolation> {
+        let command = pins.command();
+        match (self.stage, command) {
+            (Stage::PowerUp, Command::Precharge) if pins.a10() => {
+                self.stage = Stage::Refreshing(0);
+                None
+            }
+            (Stage::PowerUp, _) => Some(InitViolation::Skipped {
+                command,
+                step: InitStep::PrechargeAll,
+            }),
+            // precharging again does no harm
+            (Stage::Refreshing(_), Command::Precharge) => None,
+            (Stage::Refreshing(seen), Command::Refresh) => {
+                self.stage = Stage::Refreshing(seen + 1);
+                None
+            }
+            (Stage::Refreshing(seen), _) if seen < INIT_REFRESHES => Some(InitViolation::Skipped {
+                command,
+                step: InitStep::Refresh { seen },
+            }),
+            (Stage::Refreshing(_), Command::LoadMode) => {
+                self.stage = Stage::Done;
+                None
+            }
+            (Stage::Refreshing(_), _) => Some(InitViolation::Skipped {
+                command,
+                step: InitStep::LoadMode,
+            }),
+            (Stage::Done, _) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// tINIT is 10 clocks at 1000 MHz.
+    const TIMING: Timing = Timing {
+        t_rc: 6,
+        t_ras: 3,
+        t_rcd: 2,
+        t_rp: 2,
+        t_rrd: 2,
+        t_rfc: 6,
+        t_wr: 2,
+        t_mrd: 2,
+        t_init: 10,
+        t_ref: 64_000_000,
+    };
+
+    const PRECHARGE_ALL: (Command, u16) = (Command::Precharge, 1 << 10);
+
+    /// Issue `commands` one per clock once tINIT is over, returning the
+    /// violations.
+    fn run(commands: &[(Command, u16)]) -> Vec<InitViolation> {
+        let mut checker = InitChecker::new(&TIMING, 1000);
+        let nop = SdramPins::encode(Command::Nop, 0, 0);
+        (0..10)
+            .map(|_| nop)
+            .chain(
+                commands
+                    .iter()
+                    .map(|&(command, addr)| SdramPins::encode(command, 0, addr)),
+            )
+            .filter_map(|pins| checker.tick(&pins))
+            .collect()
+    }
+
+    #[test]
+    fn accepts_the_power_up_sequence() {
+        assert_eq!(
+            run(&[
+                PRECHARGE_ALL,
+                (Command::Refresh, 0),
+                (Command::Nop, 0),
+                (Command::Refresh, 0),
+                // more than two REFRESH commands and precharging again
+                (Command::Refresh, 0),
+                PRECHARGE_ALL,
+                (Command::LoadMode, 0x21),
+                (Command::Active, 0),
+                (Command::Write, 0),
+            ]),
+            []
+        );
+    }
+
+    #[test]
+    fn waits_for_tinit() {
+        let mut checker = InitChecker::new(&TIMING, 1000);
+        let nop = SdramPins::encode(Command::Nop, 0, 0);
+        for _ in 0..5 {
+            assert_eq!(checker.tick(&nop), None);
+        }
+        // without CKE the command is not sampled
+        let disabled = SdramPins {
+            cke: false,
+            ..SdramPins::encode(Command::Precharge, 0, 1 << 10)
+        };
+        assert_eq!(checker.tick(&disabled), None);
+        assert_eq!(
+            checker.tick(&SdramPins::encode(Command::Precharge, 0, 1 << 10)),
+            Some(InitViolation::Early {
+                command: Command::Precharge,
+                elapsed: 6,
+                required: 10,
+            })
+        );
+        // only the first violation is reported
+        assert_eq!(
+            checker.tick(&SdramPins::encode(Command::Active, 0, 0)),
+            None
+        );
+    }
+
+    #[test]
+    fn needs_precharge_all_first() {
+        assert_eq!(
+            run(&[(Command::Refresh, 0)]),
+            [InitViolation::Skipped {
+                command: Command::Refresh,
+                step: InitStep::PrechargeAll,
+            }]
+        );
+        // a single bank is not enough
+        assert_eq!(
+            run(&[(Command::Precharge, 0), (Command::Refresh, 0)]),
+            [InitViolation::Skipped {
+                command: Command::Precharge,
+                step: InitStep::PrechargeAll,
+            }]
+        );
+    }
+
+    #[test]
+    fn needs_two_refreshes() {
+        assert_eq!(
+            run(&[PRECHARGE_ALL, (Command::LoadMode, 0x21)]),
+            [InitViolation::Skipped {
+                command: Command::LoadMode,
+                step: InitStep::Refresh { seen: 0 },
+            }]
+        );
+        assert_eq!(
+            run(&[
+                PRECHARGE_ALL,
+                (Command::Refresh, 0),
+                (Command::LoadMode, 0x21)
+            ]),
+            [InitViolation::Skipped {
+                command: Command::LoadMode,
+                step: InitStep::Refresh { seen: 1 },
+            }]
+        );
+    }
+
+    #[test]
+    fn needs_load_mode_before_active() {
+        assert_eq!(
+            run(&[
+                PRECHARGE_ALL,
+                (Command::Refresh, 0),
+                (Command::Refresh, 0),
+                (Command::Active, 0),
+                (Command::LoadMode, 0x21),
+            ]),
+            [InitViolation::Skipped {
+                command: Command::Active,
+                step: InitStep::LoadMode,
+            }]
+        );
+    }
+}
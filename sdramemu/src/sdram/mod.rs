@@ -0,0 +1,150 @@
+//! The SDR SDRAM side of the controller: the pins it drives, the commands
+//! they encode, how AXI addresses map onto them, a behavioural model of the
+//! device behind them, faults to inject into it, checkers of the power-up
+//! sequence, the mode register, the timing between commands and the refresh
+//! rate, and the coverage of each chip select.
+
+use serde::Deserialize;
+
+mod address;
+mod coverage;
+mod device;
+mod fault;
+mod init;
+mod mode;
+mod part;
+mod refresh;
+mod timing;
+
+pub use address::{AddressMap, SdramAddress};
+pub use coverage::Coverage;
+pub use device::{DeviceError, SdramDevice};
+pub use fault::{Fault, FaultModel, Injection};
+pub use init::{InitChecker, InitStep, InitViolation};
+pub use mode::{
+    BurstLength, BurstType, ModeChecker, ModeRegister, ReservedMode, TransferViolation, WriteBeat,
+    WriteBurstMode,
+};
+pub use part::{Catalogue, Part, DEFAULT_PART};
+pub use refresh::{RefreshMonitor, RefreshViolation};
+pub use timing::{Timing, TimingChecker, TimingRule, TimingViolation};
+
+/// Pins of one chip select, as sampled at a rising clock edge.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SdramPins {
+    pub addr: u16,
+    pub bs: u8,
+    pub cke: bool,
+    pub cs_n: bool,
+    pub ras_n: bool,
+    pub cas_n: bool,
+    pub we_n: bool,
+    pub dqm: u8,
+    /// Data driven by the controller.
+    pub dq: u32,
+}
+
+/// Commands of the JEDEC SDR SDRAM truth table, decoded from CS#, RAS#, CAS#
+/// and WE#.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Deselect,
+    Nop,
+    BurstTerminate,
+    Read,
+    Write,
+    Active,
+    Precharge,
+    Refresh,
+    LoadMode,
+}
+
+impl SdramPins {
+    pub fn command(&self) -> Command {
+        if self.cs_n {
+            return Command::Deselect;
+        }
+        match (self.ras_n, self.cas_n, self.we_n) {
+            (true, true, true) => Command::Nop,
+            (true, true, false) => Command::BurstTerminate,
+            (true, false, true) => Command::Read,
+            (true, false, false) => Command::Write,
+            (false, true, true) => Command::Active,
+            (false, true, false) => Command::Precharge,
+            (false, false, true) => Command::Refresh,
+            (false, false, false) => Command::LoadMode,
+        }
+    }
+
+    /// A10 selects auto precharge for READ and WRITE, and all banks for
+    /// PRECHARGE.
+    pub fn a10(&self) -> bool {
+        (self.addr >> 10) & 1 != 0
+    }
+}
+
+#[cfg(test)]
+impl SdramPins {
+    /// The pins of `command` with the clock enabled, the inverse of
+    /// `SdramPins::command`.
+    pub(crate) fn encode(command: Command, bs: u8, addr: u16) -> Self {
+        let (ras_n, cas_n, we_n) = match command {
+            Command::Deselect | Command::Nop => (true, true, true),
+            Command::BurstTerminate => (true, true, false),
+            Command::Read => (true, false, true),
+            Command::Write => (true, false, false),
+            Command::Active => (false, true, true),
+            Command::Precharge => (false, true, false),
+            Command::Refresh => (false, false, true),
+            Command::LoadMode => (false, false, false),
+        };
+        Self {
+            addr,
+            bs,
+            cke: true,
+            cs_n: command == Command::Deselect,
+            ras_n,
+            cas_n,
+            we_n,
+            dqm: 0,
+            dq: 0,
+        }
+    }
+}
+
+/// Organisation of one device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Geometry {
+    pub row_width: u32,
+    pub col_width: u32,
+    pub banks: u32,
+    /// DQ width in bits.
+    pub data_width: u32,
+}
+
+impl Geometry {
+    pub fn rows(&self) -> u32 {
+        1 << self.row_width
+    }
+
+    pub fn cols(&self) -> u32 {
+        1 << self.col_width
+    }
+
+    /// Index of a cell of the array, the columns of a row are consecutive.
+    pub(crate) fn cell_index(&self, bank: u32, row: u32, col: u32) -> u64 {
+        ((bank as u64 * self.rows() as u64 + row as u64) << self.col_width) | col as u64
+    }
+
+    /// Bank, row and column of a cell index.
+    pub(crate) fn cell_location(&self, index: u64) -> (u32, u32, u32) {
+        let col = (index & (self.cols() as u64 - 1)) as u32;
+        let row = index >> self.col_width;
+        (
+            (row / self.rows() as u64) as u32,
+            (row % self.rows() as u64) as u32,
+            col,
+        )
+    }
+}
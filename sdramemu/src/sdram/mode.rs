@@ -0,0 +1,773 @@
+use std::fmt;
+
+use super::{Command, Geometry, SdramPins};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BurstLength {
+    Beats(u32),
+    /// A whole row, sequential bursts only. Ended by BURST TERMINATE.
+    FullPage,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BurstType {
+    Sequential,
+    Interleaved,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteBurstMode {
+    /// Writes burst like reads.
+    Programmed,
+    /// Writes access a single location whatever the burst length.
+    Single,
+}
+
+/// The mode register as programmed by LOAD MODE, see the JEDEC SDR SDRAM
+/// mode register definition:
+///
+/// | A12..A10 | A9          | A8..A7 | A6..A4 | A3         | A2..A0 |
+/// |----------|-------------|--------|--------|------------|--------|
+/// | reserved | write burst | 00     | CL     | burst type | BL     |
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModeRegister {
+    pub burst_length: BurstLength,
+    pub burst_type: BurstType,
+    pub cas_latency: u32,
+    pub write_burst_mode: WriteBurstMode,
+}
+
+/// A LOAD MODE value using an encoding the standard reserves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReservedMode {
+    pub value: u16,
+    pub field: &'static str,
+}
+
+impl fmt::Display for ReservedMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "LOAD MODE 0x{:04x} with a reserved {} encoding",
+            self.value, self.field
+        )
+    }
+}
+
+impl ModeRegister {
+    pub fn decode(value: u16) -> Result<Self, ReservedMode> {
+        let reserved = |field| ReservedMode { value, field };
+        let burst_type = if (value >> 3) & 1 != 0 {
+            BurstType::Interleaved
+        } else {
+            BurstType::Sequential
+        };
+        let burst_length = match value & 0b111 {
+            code @ 0..=3 => BurstLength::Beats(1 << code),
+            7 if burst_type == BurstType::Sequential => BurstLength::FullPage,
+            _ => return Err(reserved("burst length")),
+        };
+        let cas_latency = match (value >> 4) & 0b111 {
+            code @ 2..=3 => code as u32,
+            _ => return Err(reserved("CAS latency")),
+        };
+        if (value >> 7) & 0b11 != 0 {
+            return Err(reserved("operating mode"));
+        }
+        if value >> 10 != 0 {
+            return Err(reserved("A12..A10"));
+        }
+        let write_burst_mode = if (value >> 9) & 1 != 0 {
+            WriteBurstMode::Single
+        } else {
+            WriteBurstMode::Programmed
+        };
+        Ok(Self {
+            burst_length,
+            burst_type,
+            cas_latency,
+            write_burst_mode,
+        })
+    }
+
+    /// Beats of a read burst.
+    pub fn read_beats(&self, geometry: &Geometry) -> u32 {
+        match self.burst_length {
+            BurstLength::Beats(beats) => beats,
+            BurstLength::FullPage => geometry.cols(),
+        }
+    }
+
+    /// Beats of a write burst.
+    pub fn write_beats(&self, geometry: &Geometry) -> u32 {
+        match self.write_burst_mode {
+            WriteBurstMode::Programmed => self.read_beats(geometry),
+            WriteBurstMode::Single => 1,
+        }
+    }
+}
+
+impl fmt::Display for ModeRegister {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CL={}, ", self.cas_latency)?;
+        match self.burst_length {
+            BurstLength::Beats(beats) => write!(f, "BL={beats}")?,
+            BurstLength::FullPage => write!(f, "BL=full page")?,
+        }
+        write!(
+            f,
+            ", {}, {}",
+            match self.burst_type {
+                BurstType::Sequential => "sequential",
+                BurstType::Interleaved => "interleaved",
+            },
+            match self.write_burst_mode {
+                WriteBurstMode::Programmed => "burst write",
+                WriteBurstMode::Single => "single write",
+            }
+        )
+    }
+}
+
+/// A transfer the controller did not make the way the mode register says the
+/// device will.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransferViolation {
+    Reserved(ReservedMode),
+    /// READ or WRITE before the mode register was loaded.
+    ModeUnset {
+        command: Command,
+    },
+    /// The burst length differs from the beats the controller moves per
+    /// access.
+    BurstLength {
+        programmed: u32,
+        transfer: u32,
+    },
+    /// A command cut a burst short.
+    Truncated {
+        command: Command,
+        burst: Command,
+        beat: u32,
+        beats: u32,
+    },
+    /// DQM masked a beat of read data at the programmed CAS latency.
+    ReadMasked {
+        beat: u32,
+        dqm: u8,
+    },
+    /// DQM of a beat of write data differs from the strobes of the AXI write
+    /// it belongs to.
+    WriteMasked {
+        beat: u32,
+        dqm: u8,
+        expected: u8,
+    },
+}
+
+impl fmt::Display for TransferViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Reserved(reserved) => write!(f, "{reserved}"),
+            Self::ModeUnset { command } => {
+                write!(f, "{command:?} before the mode register was loaded")
+            }
+            Self::BurstLength {
+                programmed,
+                transfer,
+            } => write!(
+                f,
+                "burst length {programmed} programmed, the controller transfers {transfer} beat(s) per access"
+            ),
+            Self::Truncated {
+                command,
+                burst,
+                beat,
+                beats,
+            } => write!(
+                f,
+                "{command:?} cut the {burst:?} burst short at beat {beat} of {beats}"
+            ),
+            Self::ReadMasked { beat, dqm } => write!(
+                f,
+                "beat {beat} of the read burst masked by DQM 0b{dqm:b} at the programmed CAS latency"
+            ),
+            Self::WriteMasked {
+                beat,
+                dqm,
+                expected,
+            } => write!(
+                f,
+                "beat {beat} of the write burst driven with DQM 0b{dqm:b}, the strobes of its AXI write need 0b{expected:b}"
+            ),
+        }
+    }
+}
+
+/// The cell a beat of write data lands in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriteBeat {
+    pub bank: u32,
+    pub row: u32,
+    pub col: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Burst {
+    command: Command,
+    bank: u32,
+    start: u64,
+    beats: u32,
+}
+
+/// Decodes every LOAD MODE of one chip select and checks the controller's
+/// READ and WRITE transfers match the mode it programmed: every access is one
+/// whole burst, read data is not masked when the device drives it at the
+/// CAS latency, and write data is masked exactly where its AXI write has no
+/// strobe. Clocked once per rising edge like `SdramDevice`.
+pub struct ModeChecker {
+    geometry: Geometry,
+    /// Beats the controller moves per access, i.e. AXI beat over DQ width.
+    transfer_beats: u32,
+    mode: Option<ModeRegister>,
+    clock: u64,
+    burst: Option<Burst>,
+    /// Clock DQM has to enable each read beat at, in order.
+    read_masks: Vec<(u64, u32)>,
+    /// Open row of every bank.
+    rows: Vec<Option<u32>>,
+    /// Clock, beat and cell of every write beat still to come.
+    write_masks: Vec<(u64, u32, WriteBeat)>,
+}
+
+impl ModeChecker {
+    pub fn new(geometry: Geometry, transfer_beats: u32) -> Self {
+        Self {
+            geometry,
+            transfer_beats,
+            mode: None,
+            clock: 0,
+            burst: None,
+            read_masks: Vec::new(),
+            rows: vec![None; geometry.banks as usize],
+            write_masks: Vec::new(),
+        }
+    }
+
+    /// Check the pins sampled at a rising edge. `expected_dqm` gives the DQM
+    /// a write beat has to be driven with, `None` when the AXI write it
+    /// belongs to is not known.
+    pub fn tick(
+        &mut self,
+        pins: &SdramPins,
+        expected_dqm: impl Fn(WriteBeat) -> Option<u8>,
+    ) -> Vec<TransferViolation> {
+        let mut violations = Vec::new();
+        if pins.cke {
+            self.check(pins, &mut violations);
+            self.check_read_masks(pins, &mut violations);
+            self.check_write_masks(pins, expected_dqm, &mut violations);
+        }
+        self.clock += 1;
+        violations
+    }
+
+    fn check(&mut self, pins: &SdramPins, violations: &mut Vec<TransferViolation>) {
+        let clock = self.clock;
+        if self
+            .burst
+            .is_some_and(|burst| clock >= burst.start + burst.beats as u64)
+        {
+            self.burst = None;
+        }
+
+        let command = pins.command();
+        let bank = pins.bs as u32 % self.geometry.banks;
+        if let Some(burst) = self.burst {
+            let cuts = match command {
+                Command::Read | Command::Write | Command::BurstTerminate => true,
+                Command::Precharge => pins.a10() || burst.bank == bank,
+                _ => false,
+            };
+            // a full page burst is meant to be terminated
+            let full_page = self
+                .mode
+                .is_some_and(|mode| mode.burst_length == BurstLength::FullPage);
+            if cuts && !full_page {
+                violations.push(TransferViolation::Truncated {
+                    command,
+                    burst: burst.command,
+                    beat: (clock - burst.start) as u32,
+                    beats: burst.beats,
+                });
+            }
+            if cuts {
+                self.burst = None;
+                self.write_masks.clear();
+                if burst.command == Command::Read {
+                    // the device stops driving the burst CL clocks after the
+                    // command, the data of a WRITE takes DQ from the command
+                    // on with DQM masking the read data still in flight
+                    let cas_latency = self.mode.map_or(2, |mode| mode.cas_latency as u64);
+                    let end = match command {
+                        Command::Write => clock,
+                        _ => clock + cas_latency - 2,
+                    };
+                    self.read_masks.retain(|&(at, _)| at < end);
+                }
+            }
+        }
+
+        match command {
+            Command::Active => {
+                self.rows[bank as usize] = Some(pins.addr as u32 & (self.geometry.rows() - 1));
+            }
+            Command::LoadMode => match ModeRegister::decode(pins.addr) {
+                Ok(mode) => {
+                    let programmed = mode.read_beats(&self.geometry);
+                    if programmed != self.transfer_beats {
+                        violations.push(TransferViolation::BurstLength {
+                            programmed,
+                            transfer: self.transfer_beats,
+                        });
+                    }
+                    self.mode = Some(mode);
+                }
+                Err(reserved) => violations.push(TransferViolation::Reserved(reserved)),
+            },
+            Command::Read | Command::Write => {
+                let Some(mode) = self.mode else {
+                    violations.push(TransferViolation::ModeUnset { command });
+                    return;
+                };
+                let beats = if command == Command::Read {
+                    let beats = mode.read_beats(&self.geometry);
+                    let first = clock + mode.cas_latency as u64 - 2;
+                    self.read_masks
+                        .extend((0..beats).map(|beat| (first + beat as u64, beat)));
+                    beats
+                } else {
+                    let beats = mode.write_beats(&self.geometry);
+                    if let Some(row) = self.rows[bank as usize] {
+                        let col = pins.addr as u32 & (self.geometry.cols() - 1);
+                        self.write_masks.extend((0..beats).map(|beat| {
+                            // the burst wraps within a block of its length
+                            let offset = match mode.burst_type {
+                                BurstType::Sequential => col + beat,
+                                BurstType::Interleaved => col ^ beat,
+                            } % beats;
+                            let col = (col & !(beats - 1)) | offset;
+                            (clock + beat as u64, beat, WriteBeat { bank, row, col })
+                        }));
+                    }
+                    beats
+                };
+                self.burst = Some(Burst {
+                    command,
+                    bank,
+                    start: clock,
+                    beats,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    /// Read data is masked by DQM two clocks ahead, so with CL=2 from the
+    /// READ itself.
+    fn check_read_masks(&mut self, pins: &SdramPins, violations: &mut Vec<TransferViolation>) {
+        let clock = self.clock;
+        self.read_masks.retain(|&(at, beat)| {
+            if at == clock && pins.dqm != 0 {
+                violations.push(TransferViolation::ReadMasked {
+                    beat,
+                    dqm: pins.dqm,
+                });
+            }
+            at > clock
+        });
+    }
+
+    /// Write data is masked by DQM coincident with it, from the WRITE on.
+    fn check_write_masks(
+        &mut self,
+        pins: &SdramPins,
+        expected_dqm: impl Fn(WriteBeat) -> Option<u8>,
+        violations: &mut Vec<TransferViolation>,
+    ) {
+        let clock = self.clock;
+        let lanes = (1u8 << self.geometry.data_width.div_ceil(8)) - 1;
+        self.write_masks.retain(|&(at, beat, cell)| {
+            if at != clock {
+                return at > clock;
+            }
+            if let Some(expected) = expected_dqm(cell) {
+                let dqm = pins.dqm & lanes;
+                if dqm != expected {
+                    violations.push(TransferViolation::WriteMasked {
+                        beat,
+                        dqm,
+                        expected,
+                    });
+                }
+            }
+            false
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GEOMETRY: Geometry = Geometry {
+        row_width: 4,
+        col_width: 8,
+        banks: 4,
+        data_width: 16,
+    };
+
+    fn at(clock: u64, command: Command, bank: u8, addr: u16) -> (u64, SdramPins) {
+        (clock, SdramPins::encode(command, bank, addr))
+    }
+
+    /// A NOP with DQM set to `dqm`.
+    fn masked(clock: u64, dqm: u8) -> (u64, SdramPins) {
+        (
+            clock,
+            SdramPins {
+                dqm,
+                ..SdramPins::encode(Command::Nop, 0, 0)
+            },
+        )
+    }
+
+    /// Clock `schedule` into a checker of `transfer_beats`, NOPs in between,
+    /// until `end`.
+    fn run(
+        transfer_beats: u32,
+        schedule: &[(u64, SdramPins)],
+        end: u64,
+        expected_dqm: fn(WriteBeat) -> Option<u8>,
+    ) -> Vec<(u64, TransferViolation)> {
+        let mut checker = ModeChecker::new(GEOMETRY, transfer_beats);
+        (0..end)
+            .flat_map(|clock| {
+                let pins = schedule
+                    .iter()
+                    .find(|&&(at, _)| at == clock)
+                    .map_or(SdramPins::encode(Command::Nop, 0, 0), |&(_, pins)| pins);
+                checker
+                    .tick(&pins, expected_dqm)
+                    .into_iter()
+                    .map(move |violation| (clock, violation))
+            })
+            .collect()
+    }
+
+    fn unknown(_: WriteBeat) -> Option<u8> {
+        None
+    }
+
+    #[test]
+    fn decodes_the_mode_register() {
+        assert_eq!(
+            ModeRegister::decode(0x21),
+            Ok(ModeRegister {
+                burst_length: BurstLength::Beats(2),
+                burst_type: BurstType::Sequential,
+                cas_latency: 2,
+                write_burst_mode: WriteBurstMode::Programmed,
+            })
+        );
+        let mode = ModeRegister::decode(0x237).unwrap();
+        assert_eq!(mode.burst_length, BurstLength::FullPage);
+        assert_eq!(mode.cas_latency, 3);
+        assert_eq!(mode.write_burst_mode, WriteBurstMode::Single);
+        assert_eq!(mode.read_beats(&GEOMETRY), 256);
+        assert_eq!(mode.write_beats(&GEOMETRY), 1);
+        let mode = ModeRegister::decode(0x2b).unwrap();
+        assert_eq!(mode.burst_type, BurstType::Interleaved);
+        assert_eq!(mode.write_beats(&GEOMETRY), 8);
+    }
+
+    #[test]
+    fn rejects_reserved_encodings() {
+        for (value, field) in [
+            (0x24, "burst length"),
+            (0x26, "burst length"),
+            // full page is sequential only
+            (0x2f, "burst length"),
+            (0x01, "CAS latency"),
+            (0x11, "CAS latency"),
+            (0x41, "CAS latency"),
+            (0x71, "CAS latency"),
+            (0xa1, "operating mode"),
+            (0x121, "operating mode"),
+            (0x421, "A12..A10"),
+            (0x1021, "A12..A10"),
+        ] {
+            assert_eq!(
+                ModeRegister::decode(value),
+                Err(ReservedMode { value, field }),
+                "0x{value:x}"
+            );
+        }
+    }
+
+    #[test]
+    fn checks_the_burst_length_and_mode() {
+        assert_eq!(
+            run(
+                2,
+                &[
+                    at(0, Command::Read, 0, 0),
+                    at(2, Command::LoadMode, 0, 0x22)
+                ],
+                4,
+                unknown
+            ),
+            [
+                (
+                    0,
+                    TransferViolation::ModeUnset {
+                        command: Command::Read
+                    }
+                ),
+                (
+                    2,
+                    TransferViolation::BurstLength {
+                        programmed: 4,
+                        transfer: 2
+                    }
+                ),
+            ]
+        );
+        assert_eq!(
+            run(2, &[at(0, Command::LoadMode, 0, 0x24)], 1, unknown),
+            [(
+                0,
+                TransferViolation::Reserved(ReservedMode {
+                    value: 0x24,
+                    field: "burst length"
+                })
+            )]
+        );
+    }
+
+    #[test]
+    fn checks_dqm_at_the_cas_latency() {
+        // DQM masks read data two clocks ahead, so from the READ with CL=2
+        let setup = [
+            at(0, Command::LoadMode, 0, 0x21),
+            at(2, Command::Active, 0, 0),
+            at(4, Command::Read, 0, 0),
+        ];
+        let schedule = [setup.as_slice(), &[masked(6, 0b11)]].concat();
+        assert_eq!(run(2, &schedule, 10, unknown), []);
+        let schedule = [setup.as_slice(), &[masked(5, 0b01)]].concat();
+        assert_eq!(
+            run(2, &schedule, 10, unknown),
+            [(5, TransferViolation::ReadMasked { beat: 1, dqm: 0b01 })]
+        );
+        // and a clock later with CL=3
+        let setup = [
+            at(0, Command::LoadMode, 0, 0x31),
+            at(2, Command::Active, 0, 0),
+            at(4, Command::Read, 0, 0),
+        ];
+        let schedule = [setup.as_slice(), &[masked(4, 0b11)]].concat();
+        assert_eq!(run(2, &schedule, 10, unknown), []);
+        let schedule = [setup.as_slice(), &[masked(5, 0b10)]].concat();
+        assert_eq!(
+            run(2, &schedule, 10, unknown),
+            [(5, TransferViolation::ReadMasked { beat: 0, dqm: 0b10 })]
+        );
+    }
+
+    #[test]
+    fn checks_write_dqm_against_the_strobes() {
+        // columns 4 and 5 of row 3 in bank 1, the upper byte of 5 unstrobed
+        fn strobes(beat: WriteBeat) -> Option<u8> {
+            match beat {
+                WriteBeat {
+                    bank: 1,
+                    row: 3,
+                    col: 4,
+                } => Some(0b00),
+                WriteBeat {
+                    bank: 1,
+                    row: 3,
+                    col: 5,
+                } => Some(0b10),
+                _ => None,
+            }
+        }
+        let setup = [
+            at(0, Command::LoadMode, 0, 0x21),
+            at(2, Command::Active, 1, 3),
+        ];
+        let schedule = [
+            setup.as_slice(),
+            &[at(4, Command::Write, 1, 4), masked(5, 0b10)],
+        ]
+        .concat();
+        assert_eq!(run(2, &schedule, 8, strobes), []);
+        let schedule = [setup.as_slice(), &[at(4, Command::Write, 1, 4)]].concat();
+        assert_eq!(
+            run(2, &schedule, 8, strobes),
+            [(
+                5,
+                TransferViolation::WriteMasked {
+                    beat: 1,
+                    dqm: 0,
+                    expected: 0b10
+                }
+            )]
+        );
+        // the burst wraps within its block, column 5 comes first
+        let write = SdramPins {
+            dqm: 0b10,
+            ..SdramPins::encode(Command::Write, 1, 5)
+        };
+        let schedule = [setup.as_slice(), &[(4, write)]].concat();
+        assert_eq!(run(2, &schedule, 8, strobes), []);
+    }
+
+    #[test]
+    fn flags_truncated_bursts_and_drops_their_beats() {
+        // BL4, CL3: DQM covers read data from the clock after the READ
+        let setup = [
+            at(0, Command::LoadMode, 0, 0x32),
+            at(2, Command::Active, 0, 0),
+            at(4, Command::Read, 0, 0),
+        ];
+        // the device stops driving CL clocks after the PRECHARGE
+        let schedule = [
+            setup.as_slice(),
+            &[
+                at(5, Command::Precharge, 0, 0),
+                masked(7, 0b11),
+                masked(8, 0b11),
+            ],
+        ]
+        .concat();
+        assert_eq!(
+            run(4, &schedule, 12, unknown),
+            [(
+                5,
+                TransferViolation::Truncated {
+                    command: Command::Precharge,
+                    burst: Command::Read,
+                    beat: 1,
+                    beats: 4
+                }
+            )]
+        );
+        // beats up to then are still checked
+        let schedule = [
+            setup.as_slice(),
+            &[masked(5, 0b01), at(6, Command::Precharge, 0, 0)],
+        ]
+        .concat();
+        assert_eq!(run(4, &schedule, 12, unknown).len(), 2);
+
+        // a WRITE masks the read data still in flight
+        let schedule = [
+            setup.as_slice(),
+            &[
+                at(6, Command::Write, 0, 8),
+                masked(7, 0b11),
+                masked(8, 0b11),
+            ],
+        ]
+        .concat();
+        assert_eq!(
+            run(4, &schedule, 12, unknown),
+            [(
+                6,
+                TransferViolation::Truncated {
+                    command: Command::Write,
+                    burst: Command::Read,
+                    beat: 2,
+                    beats: 4
+                }
+            )]
+        );
+
+        // a READ replaces the rest of the burst with its own
+        let schedule = [
+            setup.as_slice(),
+            &[at(6, Command::Read, 0, 8), masked(8, 0b11)],
+        ]
+        .concat();
+        assert_eq!(
+            run(4, &schedule, 12, unknown),
+            [
+                (
+                    6,
+                    TransferViolation::Truncated {
+                        command: Command::Read,
+                        burst: Command::Read,
+                        beat: 2,
+                        beats: 4
+                    }
+                ),
+                (8, TransferViolation::ReadMasked { beat: 1, dqm: 0b11 })
+            ]
+        );
+
+        // the rest of a cut write burst is not checked against its strobes
+        let setup = [
+            at(0, Command::LoadMode, 0, 0x32),
+            at(2, Command::Active, 0, 0),
+            at(4, Command::Write, 0, 0),
+        ];
+        let schedule = [
+            setup.as_slice(),
+            &[at(6, Command::BurstTerminate, 0, 0), masked(7, 0b11)],
+        ]
+        .concat();
+        assert_eq!(
+            run(4, &schedule, 10, |_| Some(0)),
+            [(
+                6,
+                TransferViolation::Truncated {
+                    command: Command::BurstTerminate,
+                    burst: Command::Write,
+                    beat: 2,
+                    beats: 4
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn full_page_bursts_end_at_burst_terminate() {
+        let setup = [
+            at(0, Command::LoadMode, 0, 0x37),
+            at(2, Command::Active, 0, 0),
+            at(4, Command::Read, 0, 0),
+        ];
+        let schedule = [
+            setup.as_slice(),
+            &[
+                at(8, Command::BurstTerminate, 0, 0),
+                masked(9, 0b11),
+                masked(10, 0b11),
+            ],
+        ]
+        .concat();
+        assert_eq!(run(256, &schedule, 300, unknown), []);
+        // until then every beat is checked
+        let schedule = [
+            setup.as_slice(),
+            &[masked(8, 0b11), at(9, Command::BurstTerminate, 0, 0)],
+        ]
+        .concat();
+        assert_eq!(
+            run(256, &schedule, 300, unknown),
+            [(8, TransferViolation::ReadMasked { beat: 3, dqm: 0b11 })]
+        );
+    }
+}
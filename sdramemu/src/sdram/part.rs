@@ -0,0 +1,261 @@
+//! Catalogue of SDR SDRAM parts, selected with `+sdram-part=<name>`.
+//!
+//! The built-in parts can be extended, or overridden by name, with a JSON list
+//! given with `+sdram-parts=<path>`:
+//!
+//! ```json
+//! [
+//!   {
+//!     "name": "W9825G6KH-6",
+//!     "geometry": { "rowWidth": 13, "colWidth": 9, "banks": 4, "dataWidth": 16 },
+//!     "timing": {
+//!       "tRC": 60, "tRAS": 42, "tRCD": 15, "tRP": 15, "tRRD": 12, "tRFC": 60,
+//!       "tWR": 2, "tMRD": 2, "tINIT": 100000, "tREF": 64000000
+//!     }
+//!   }
+//! ]
+//! ```
+//!
+//! Times are in ns, except `tWR` and `tMRD` which are in clocks. Every key is
+//! required.
+
+use anyhow::{ensure, Context, Result};
+use common::rtl_config::SdramParameter;
+use serde::Deserialize;
+use std::path::Path;
+
+use super::{Geometry, Timing};
+
+/// The part the testbench instantiates the vendor model of.
+pub const DEFAULT_PART: &str = "W9825G6KH";
+
+/// Row and column address bits and banks the controller slices an AXI
+/// address into, `SDRAM_ROW_W`, `SDRAM_COL_W` and `SDRAM_BANK_N` in the RTL.
+/// Its config only sets the DQ and chip select widths.
+const CONTROLLER_ROW_WIDTH: u32 = 13;
+const CONTROLLER_COL_WIDTH: u32 = 9;
+const CONTROLLER_BANKS: u32 = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Part {
+    pub name: String,
+    pub geometry: Geometry,
+    pub timing: Timing,
+}
+
+/// 256Mb x16 parts of 8192 rows of 512 columns in 4 banks, the only
+/// organisation the controller drives. tINIT is the JEDEC 100µs for all of
+/// them, the wait the controller makes.
+fn builtin() -> Vec<Part> {
+    let x16 = Geometry {
+        row_width: CONTROLLER_ROW_WIDTH,
+        col_width: CONTROLLER_COL_WIDTH,
+        banks: CONTROLLER_BANKS,
+        data_width: 16,
+    };
+    let part = |name: &str, geometry, timing| Part {
+        name: name.to_owned(),
+        geometry,
+        timing,
+    };
+    vec![
+        // Winbond W9825G6KH-6, 256Mb
+        part(
+            "W9825G6KH",
+            x16,
+            Timing {
+                t_rc: 60,
+                t_ras: 42,
+                t_rcd: 15,
+                t_rp: 15,
+                t_rrd: 12,
+                t_rfc: 60,
+                t_wr: 2,
+                t_mrd: 2,
+                t_init: 100_000,
+                t_ref: 64_000_000,
+            },
+        ),
+        // ISSI IS42S16160J-7, 256Mb
+        part(
+            "IS42S16160",
+            x16,
+            Timing {
+                t_rc: 63,
+                t_ras: 42,
+                t_rcd: 15,
+                t_rp: 15,
+                t_rrd: 14,
+                t_rfc: 63,
+                t_wr: 2,
+                t_mrd: 2,
+                t_init: 100_000,
+                t_ref: 64_000_000,
+            },
+        ),
+        // Micron MT48LC16M16A2-75, 256Mb
+        part(
+            "MT48LC16M16A2",
+            x16,
+            Timing {
+                t_rc: 66,
+                t_ras: 44,
+                t_rcd: 20,
+                t_rp: 20,
+                t_rrd: 15,
+                t_rfc: 66,
+                t_wr: 2,
+                t_mrd: 2,
+                t_init: 100_000,
+                t_ref: 64_000_000,
+            },
+        ),
+    ]
+}
+
+impl Part {
+    /// Check the part fits the pins and the device model.
+    fn validate(&self) -> Result<()> {
+        let geometry = &self.geometry;
+        ensure!(
+            geometry.banks.is_power_of_two() && geometry.banks <= 4,
+            "part {}: {} banks, BA selects at most 4",
+            self.name,
+            geometry.banks
+        );
+        ensure!(
+            (1..=13).contains(&geometry.row_width),
+            "part {}: {} row address bits, the address bus has 13",
+            self.name,
+            geometry.row_width
+        );
+        // A10 is the auto precharge bit of READ and WRITE
+        ensure!(
+            (1..=10).contains(&geometry.col_width),
+            "part {}: {} column address bits, at most 10 fit below A10",
+            self.name,
+            geometry.col_width
+        );
+        ensure!(
+            matches!(geometry.data_width, 8 | 16 | 32),
+            "part {}: {}-bit DQ, only 8, 16 or 32 bits are supported",
+            self.name,
+            geometry.data_width
+        );
+        ensure!(
+            self.timing.t_ref > 0,
+            "part {}: tREF must not be zero",
+            self.name
+        );
+        Ok(())
+    }
+
+    /// Check the part is organised the way the controller addresses it, a
+    /// part that is not would have the checkers and the device model decode
+    /// other cells than the RTL selects.
+    pub fn check_controller(&self, sdram: &SdramParameter) -> Result<()> {
+        let geometry = &self.geometry;
+        ensure!(
+            geometry.data_width == sdram.data_width,
+            "sdram part {} does not match the {}-bit DQ of the controller",
+            self.name,
+            sdram.data_width
+        );
+        ensure!(
+            (geometry.row_width, geometry.col_width, geometry.banks)
+                == (CONTROLLER_ROW_WIDTH, CONTROLLER_COL_WIDTH, CONTROLLER_BANKS),
+            "sdram part {} has {} row and {} column address bits and {} banks, the controller addresses {CONTROLLER_ROW_WIDTH}, {CONTROLLER_COL_WIDTH} and {CONTROLLER_BANKS}",
+            self.name,
+            geometry.row_width,
+            geometry.col_width,
+            geometry.banks
+        );
+        Ok(())
+    }
+}
+
+pub struct Catalogue {
+    parts: Vec<Part>,
+}
+
+impl Default for Catalogue {
+    fn default() -> Self {
+        Self { parts: builtin() }
+    }
+}
+
+impl Catalogue {
+    /// Add the parts of a JSON file, replacing those of the same name.
+    pub fn load(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read sdram parts `{}`", path.display()))?;
+        let parts: Vec<Part> = serde_json::from_str(&content)
+            .with_context(|| format!("failed to parse sdram parts `{}`", path.display()))?;
+        for part in parts {
+            part.validate()
+                .with_context(|| format!("invalid sdram part in `{}`", path.display()))?;
+            self.parts.retain(|other| other.name != part.name);
+            self.parts.push(part);
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Result<&Part> {
+        self.parts
+            .iter()
+            .find(|part| part.name == name)
+            .with_context(|| {
+                let names: Vec<&str> = self.parts.iter().map(|part| part.name.as_str()).collect();
+                format!(
+                    "unknown sdram part `{name}`, known parts: {}",
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SDRAM: SdramParameter = SdramParameter {
+        data_width: 16,
+        cs_width: 1,
+    };
+
+    #[test]
+    fn builtin_parts_fit_the_controller() {
+        let catalogue = Catalogue::default();
+        catalogue.get(DEFAULT_PART).unwrap();
+        for part in &catalogue.parts {
+            part.validate().unwrap();
+            part.check_controller(&SDRAM).unwrap();
+        }
+    }
+
+    #[test]
+    fn rejects_parts_the_controller_cannot_address() {
+        let part = Catalogue::default().get(DEFAULT_PART).unwrap().clone();
+        let organised = |row_width, col_width, banks, data_width| Part {
+            geometry: Geometry {
+                row_width,
+                col_width,
+                banks,
+                data_width,
+            },
+            ..part.clone()
+        };
+        // MT48LC32M16A2, MT48LC8M16A2, two banks and x8
+        for other in [
+            organised(13, 10, 4, 16),
+            organised(12, 9, 4, 16),
+            organised(13, 9, 2, 16),
+            organised(13, 9, 4, 8),
+        ] {
+            other.validate().unwrap();
+            assert!(other.check_controller(&SDRAM).is_err(), "{other:?}");
+        }
+    }
+}
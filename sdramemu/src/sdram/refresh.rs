@@ -0,0 +1,285 @@
+use std::fmt;
+
+use super::{Command, Geometry, SdramPins, Timing};
+
+/// REFRESH commands JEDEC lets a controller postpone, to be caught up on
+/// later.
+const MAX_POSTPONED: u64 = 8;
+
+/// The controller has fallen behind the average refresh interval by more than
+/// it may postpone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefreshViolation {
+    /// REFRESH commands owed at the average interval.
+    pub owed: u64,
+    /// Clocks since the last REFRESH, `None` if there was none since
+    /// initialization.
+    pub since_last: Option<u64>,
+}
+
+impl fmt::Display for RefreshViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "refresh late: {} REFRESH command(s) owed, ", self.owed)?;
+        match self.since_last {
+            Some(since_last) => write!(
+                f,
+                "the last one {since_last} clock(s) ago, at most {MAX_POSTPONED} may be postponed"
+            ),
+            None => write!(f, "none issued since initialization"),
+        }
+    }
+}
+
+/// Counts the REFRESH commands of one chip select against simulated time,
+/// clocked once per rising edge like `SdramDevice`.
+///
+/// Every row has to be refreshed within tREF, so one REFRESH is due every
+/// tREF / rows on average. Time is counted from the end of initialization,
+/// the first LOAD MODE, so the REFRESH commands of the power-up sequence do
+/// not pay into it.
+pub struct RefreshMonitor {
+    /// tREF in clocks.
+    period: u64,
+    rows: u64,
+    clock: u64,
+    /// Clock initialization ended at.
+    start: Option<u64>,
+    last: Option<u64>,
+    count: u64,
+    /// REFRESH commands since initialization.
+    paid: u64,
+    longest_gap: u64,
+    /// Whether the current shortfall was reported, until it is caught up.
+    late: bool,
+}
+
+impl RefreshMonitor {
+    pub fn new(geometry: Geometry, timing: &Timing, mhz: u64) -> Self {
+        Self {
+            period: (timing.t_ref * mhz).div_ceil(1000),
+            rows: geometry.rows() as u64,
+            clock: 0,
+            start: None,
+            last: None,
+            count: 0,
+            paid: 0,
+            longest_gap: 0,
+            late: false,
+        }
+    }
+
+    /// Count the command sampled at a rising edge. A shortfall is reported
+    /// once, when it first exceeds what may be postponed, and again only after
+    /// the controller has caught up.
+    pub fn tick(&mut self, pins: &SdramPins) -> Option<RefreshViolation> {
+        let clock = self.clock;
+        self.clock += 1;
+        match pins.command() {
+            Command::Refresh if pins.cke => {
+                if let Some(last) = self.last {
+                    self.longest_gap = self.longest_gap.max(clock - last);
+                }
+                self.last = Some(clock);
+                self.count += 1;
+                if self.start.is_some() {
+                    self.paid += 1;
+                }
+            }
+            Command::LoadMode if pins.cke => {
+                self.start.get_or_insert(clock);
+            }
+            _ => {}
+        }
+
+        let owed = self.owed(clock)?;
+        if owed == 0 {
+            self.late = false;
+        }
+        if owed <= MAX_POSTPONED || self.late {
+            return None;
+        }
+        self.late = true;
+        Some(RefreshViolation {
+            owed,
+            since_last: self.since_last(clock),
+        })
+    }
+
+    /// Check at the end of the run that a chip select initialized more than
+    /// the average interval ago saw a REFRESH, a run too short to go past
+    /// what may be postponed would not catch one that never refreshes.
+    pub fn finish(&self) -> Option<RefreshViolation> {
+        let clock = self.clock.checked_sub(1)?;
+        let owed = self.owed(clock)?;
+        if self.late || self.paid > 0 || owed == 0 {
+            return None;
+        }
+        Some(RefreshViolation {
+            owed,
+            since_last: None,
+        })
+    }
+
+    /// REFRESH commands owed at `clock`, `None` before initialization ended.
+    fn owed(&self, clock: u64) -> Option<u64> {
+        let due = (clock - self.start?) * self.rows / self.period;
+        Some(due.saturating_sub(self.paid))
+    }
+
+    fn since_last(&self, clock: u64) -> Option<u64> {
+        let start = self.start?;
+        self.last
+            .filter(|&last| last >= start)
+            .map(|last| clock - last)
+    }
+
+    /// REFRESH commands seen so far.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Average interval between REFRESH commands the device needs, in clocks.
+    pub fn interval(&self) -> f64 {
+        self.period as f64 / self.rows as f64
+    }
+
+    /// Longest interval between two REFRESH commands in clocks, counting the
+    /// one still open.
+    pub fn longest_gap(&self) -> u64 {
+        let open = self.last.map_or(0, |last| self.clock - 1 - last);
+        self.longest_gap.max(open)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GEOMETRY: Geometry = Geometry {
+        row_width: 4,
+        col_width: 8,
+        banks: 4,
+        data_width: 16,
+    };
+
+    /// At 1000 MHz, one REFRESH is due every 10 clocks for the 16 rows.
+    const TIMING: Timing = Timing {
+        t_rc: 6,
+        t_ras: 3,
+        t_rcd: 2,
+        t_rp: 2,
+        t_rrd: 2,
+        t_rfc: 6,
+        t_wr: 2,
+        t_mrd: 2,
+        t_init: 0,
+        t_ref: 160,
+    };
+
+    /// Clock `clocks` edges into `monitor`, issuing `command` at each clock
+    /// it lists and NOPs otherwise, returning the violations and their clock.
+    fn run(
+        monitor: &mut RefreshMonitor,
+        commands: &[(u64, Command)],
+        clocks: std::ops::Range<u64>,
+    ) -> Vec<(u64, RefreshViolation)> {
+        clocks
+            .filter_map(|clock| {
+                let command = commands
+                    .iter()
+                    .find(|&&(at, _)| at == clock)
+                    .map_or(Command::Nop, |&(_, command)| command);
+                let violation = monitor.tick(&SdramPins::encode(command, 0, 0x21))?;
+                Some((clock, violation))
+            })
+            .collect()
+    }
+
+    fn monitor() -> RefreshMonitor {
+        RefreshMonitor::new(GEOMETRY, &TIMING, 1000)
+    }
+
+    #[test]
+    fn accepts_refreshes_on_time() {
+        let mut monitor = monitor();
+        // the REFRESH commands of the power-up sequence do not pay in
+        let mut commands = vec![
+            (0, Command::Refresh),
+            (1, Command::Refresh),
+            (2, Command::LoadMode),
+        ];
+        commands.extend((1..20).map(|n| (2 + n * 10, Command::Refresh)));
+        assert_eq!(run(&mut monitor, &commands, 0..200), []);
+        assert_eq!(monitor.finish(), None);
+        assert_eq!(monitor.count(), 21);
+        assert_eq!(monitor.longest_gap(), 11);
+        assert_eq!(monitor.interval(), 10.0);
+    }
+
+    #[test]
+    fn allows_postponing_eight_refreshes() {
+        let mut monitor = monitor();
+        let mut commands = vec![(0, Command::LoadMode)];
+        // caught up in a burst after falling 8 behind
+        commands.extend((0..8).map(|n| (85 + n, Command::Refresh)));
+        assert_eq!(run(&mut monitor, &commands, 0..100), []);
+        // the one still open
+        assert_eq!(monitor.longest_gap(), 7);
+    }
+
+    #[test]
+    fn reports_a_late_refresh_once() {
+        let mut monitor = monitor();
+        let commands = [(0, Command::LoadMode), (5, Command::Refresh)];
+        assert_eq!(
+            run(&mut monitor, &commands, 0..300),
+            [(
+                100,
+                RefreshViolation {
+                    owed: 9,
+                    since_last: Some(95),
+                }
+            )]
+        );
+        // already reported
+        assert_eq!(monitor.finish(), None);
+
+        // reported again only after catching up
+        let commands: Vec<_> = (300..340).map(|clock| (clock, Command::Refresh)).collect();
+        assert_eq!(run(&mut monitor, &commands, 300..340), []);
+        assert_eq!(
+            run(&mut monitor, &[], 340..600),
+            [(
+                500,
+                RefreshViolation {
+                    owed: 9,
+                    since_last: Some(161),
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn finish_catches_a_chip_select_never_refreshed() {
+        let mut monitor = monitor();
+        // too short to go past what may be postponed
+        assert_eq!(run(&mut monitor, &[(0, Command::LoadMode)], 0..30), []);
+        assert_eq!(
+            monitor.finish(),
+            Some(RefreshViolation {
+                owed: 2,
+                since_last: None,
+            })
+        );
+
+        let mut monitor = self::monitor();
+        let commands = [(0, Command::LoadMode), (20, Command::Refresh)];
+        run(&mut monitor, &commands, 0..30);
+        assert_eq!(monitor.finish(), None);
+
+        // nothing owed before initialization ended
+        let mut monitor = self::monitor();
+        run(&mut monitor, &[], 0..30);
+        assert_eq!(monitor.finish(), None);
+    }
+}
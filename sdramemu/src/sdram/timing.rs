@@ -0,0 +1,681 @@
+use serde::Deserialize;
+use std::fmt;
+
+use super::{Command, Geometry, ModeRegister, SdramPins};
+
+/// Minimum intervals between commands, as the datasheet gives them. Named
+/// after the datasheet symbols in JSON, e.g. `tRCD`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Timing {
+    /// ACTIVE to ACTIVE of the same bank, in ns.
+    #[serde(rename = "tRC")]
+    pub t_rc: u64,
+    /// ACTIVE to PRECHARGE, in ns.
+    #[serde(rename = "tRAS")]
+    pub t_ras: u64,
+    /// ACTIVE to READ or WRITE, in ns.
+    #[serde(rename = "tRCD")]
+    pub t_rcd: u64,
+    /// PRECHARGE to ACTIVE, in ns.
+    #[serde(rename = "tRP")]
+    pub t_rp: u64,
+    /// ACTIVE to ACTIVE of another bank, in ns.
+    #[serde(rename = "tRRD")]
+    pub t_rrd: u64,
+    /// REFRESH to any command, in ns.
+    #[serde(rename = "tRFC")]
+    pub t_rfc: u64,
+    /// Last write data to PRECHARGE, in clocks.
+    #[serde(rename = "tWR")]
+    pub t_wr: u64,
+    /// LOAD MODE to any command, in clocks.
+    #[serde(rename = "tMRD")]
+    pub t_mrd: u64,
+    /// Power-up wait before the first command, in ns.
+    #[serde(rename = "tINIT")]
+    pub t_init: u64,
+    /// Period every row must be refreshed within, in ns. One REFRESH
+    /// refreshes a row of every bank.
+    #[serde(rename = "tREF")]
+    pub t_ref: u64,
+}
+
+/// A timing parameter of the JEDEC SDR SDRAM standard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimingRule {
+    Rc,
+    Ras,
+    Rcd,
+    Rp,
+    Rrd,
+    Rfc,
+    Wr,
+    Mrd,
+    /// READ to WRITE, the read burst must have left DQ.
+    CasLatency,
+}
+
+impl fmt::Display for TimingRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Rc => "tRC",
+            Self::Ras => "tRAS",
+            Self::Rcd => "tRCD",
+            Self::Rp => "tRP",
+            Self::Rrd => "tRRD",
+            Self::Rfc => "tRFC",
+            Self::Wr => "tWR",
+            Self::Mrd => "tMRD",
+            Self::CasLatency => "CL",
+        })
+    }
+}
+
+/// A command issued before a timing parameter allowed it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimingViolation {
+    pub rule: TimingRule,
+    pub command: Command,
+    pub bank: Option<u32>,
+    /// The earlier command the interval is measured from.
+    pub since: Command,
+    /// Clocks between the two commands, for auto precharge up to the
+    /// precharge the READ or WRITE implies.
+    pub elapsed: u64,
+    pub required: u64,
+}
+
+impl fmt::Display for TimingViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} violated: {:?}", self.rule, self.command)?;
+        if let Some(bank) = self.bank {
+            write!(f, " to bank {bank}")?;
+        }
+        write!(
+            f,
+            " {} clock(s) after {:?}, {} required",
+            self.elapsed, self.since, self.required
+        )
+    }
+}
+
+/// Timing parameters in clocks.
+#[derive(Debug, Clone, Copy)]
+struct Clocks {
+    rc: u64,
+    ras: u64,
+    rcd: u64,
+    rp: u64,
+    rrd: u64,
+    rfc: u64,
+    wr: u64,
+    mrd: u64,
+}
+
+impl Clocks {
+    fn new(timing: &Timing, mhz: u64) -> Self {
+        let clocks = |ns: u64| (ns * mhz).div_ceil(1000);
+        Self {
+            rc: clocks(timing.t_rc),
+            ras: clocks(timing.t_ras),
+            rcd: clocks(timing.t_rcd),
+            rp: clocks(timing.t_rp),
+            rrd: clocks(timing.t_rrd),
+            rfc: clocks(timing.t_rfc),
+            wr: timing.t_wr,
+            mrd: timing.t_mrd,
+        }
+    }
+}
+
+/// Clock of the last command of each kind to a bank.
+#[derive(Debug, Clone, Copy, Default)]
+struct BankHistory {
+    active: Option<u64>,
+    /// Explicit or automatic, the latter may lie in the future.
+    precharge: Option<u64>,
+    /// Clock of the last write data beat DQM let through.
+    write_data: Option<u64>,
+}
+
+/// A write burst still taking data.
+#[derive(Debug, Clone, Copy)]
+struct WriteBurst {
+    bank: u32,
+    /// Clock of the last beat if nothing cuts the burst short.
+    end: u64,
+    auto_precharge: bool,
+}
+
+/// Checks the commands of one chip select against the timing parameters,
+/// clocked once per rising edge like `SdramDevice`.
+pub struct TimingChecker {
+    geometry: Geometry,
+    clocks: Clocks,
+    mode: Option<ModeRegister>,
+    clock: u64,
+    banks: Vec<BankHistory>,
+    /// Clock and bank of the last ACTIVE.
+    active: Option<(u64, u32)>,
+    refresh: Option<u64>,
+    load_mode: Option<u64>,
+    read: Option<u64>,
+    write: Option<WriteBurst>,
+}
+
+impl TimingChecker {
+    pub fn new(geometry: Geometry, timing: &Timing, mhz: u64) -> Self {
+        Self {
+            geometry,
+            clocks: Clocks::new(timing, mhz),
+            mode: None,
+            clock: 0,
+            banks: vec![BankHistory::default(); geometry.banks as usize],
+            active: None,
+            refresh: None,
+            load_mode: None,
+            read: None,
+            write: None,
+        }
+    }
+
+    /// Check the command sampled at a rising edge.
+    pub fn tick(&mut self, pins: &SdramPins) -> Vec<TimingViolation> {
+        let mut violations = Vec::new();
+        if pins.cke {
+            self.cut_write(pins);
+            self.check(pins, &mut violations);
+            self.write_beat(pins);
+        }
+        self.clock += 1;
+        violations
+    }
+
+    /// End the write burst in progress if the command interrupts it, data
+    /// coincident with the command no longer belongs to the burst.
+    fn cut_write(&mut self, pins: &SdramPins) {
+        let Some(write) = self.write else {
+            return;
+        };
+        let bank = pins.bs as u32 % self.geometry.banks;
+        let cuts = match pins.command() {
+            Command::Read | Command::Write | Command::BurstTerminate => true,
+            Command::Precharge => pins.a10() || write.bank == bank,
+            _ => false,
+        };
+        if self.clock > write.end || !cuts {
+            return;
+        }
+        self.write = None;
+        if write.auto_precharge {
+            // precharge starts tWR after the last beat actually written
+            let history = &mut self.banks[write.bank as usize];
+            history.precharge = Some(
+                history
+                    .write_data
+                    .map_or(self.clock, |last| last + self.clocks.wr),
+            );
+        }
+    }
+
+    /// Record a beat of the write burst in progress unless DQM masks all of
+    /// its bytes, a masked beat does not count towards tWR.
+    fn write_beat(&mut self, pins: &SdramPins) {
+        let Some(write) = self.write else {
+            return;
+        };
+        if self.clock > write.end {
+            self.write = None;
+            return;
+        }
+        let driven =
+            (0..self.geometry.data_width.div_ceil(8)).any(|byte| (pins.dqm >> byte) & 1 == 0);
+        if driven {
+            self.banks[write.bank as usize].write_data = Some(self.clock);
+        }
+    }
+
+    fn check(&mut self, pins: &SdramPins, violations: &mut Vec<TimingViolation>) {
+        let command = pins.command();
+        if matches!(command, Command::Deselect | Command::Nop) {
+            return;
+        }
+        let bank = pins.bs as u32 % self.geometry.banks;
+        let clock = self.clock;
+        let clocks = self.clocks;
+        let mut require = |rule, bank, since, at: Option<u64>, required| {
+            let Some(at) = at else {
+                return;
+            };
+            let elapsed = clock.saturating_sub(at);
+            if elapsed < required {
+                violations.push(TimingViolation {
+                    rule,
+                    command,
+                    bank,
+                    since,
+                    elapsed,
+                    required,
+                });
+            }
+        };
+
+        require(
+            TimingRule::Mrd,
+            None,
+            Command::LoadMode,
+            self.load_mode,
+            clocks.mrd,
+        );
+        let history = self.banks[bank as usize];
+        // bank and clock of the precharge a READ or WRITE implies
+        let mut auto_precharge = None;
+        match command {
+            Command::Active => {
+                require(
+                    TimingRule::Rc,
+                    Some(bank),
+                    Command::Active,
+                    history.active,
+                    clocks.rc,
+                );
+                require(
+                    TimingRule::Rp,
+                    Some(bank),
+                    Command::Precharge,
+                    history.precharge,
+                    clocks.rp,
+                );
+                if let Some((at, _)) = self.active.filter(|&(_, other)| other != bank) {
+                    require(
+                        TimingRule::Rrd,
+                        Some(bank),
+                        Command::Active,
+                        Some(at),
+                        clocks.rrd,
+                    );
+                }
+                require(
+                    TimingRule::Rfc,
+                    None,
+                    Command::Refresh,
+                    self.refresh,
+                    clocks.rfc,
+                );
+                self.banks[bank as usize].active = Some(clock);
+                self.active = Some((clock, bank));
+            }
+            Command::Read | Command::Write => {
+                require(
+                    TimingRule::Rcd,
+                    Some(bank),
+                    Command::Active,
+                    history.active,
+                    clocks.rcd,
+                );
+                let geometry = self.geometry;
+                let read_beats = self
+                    .mode
+                    .map_or(1, |mode| mode.read_beats(&geometry) as u64);
+                if command == Command::Write {
+                    // the last beat of the read must be off DQ before the
+                    // first one of the write
+                    let cas_latency = self.mode.map_or(0, |mode| mode.cas_latency as u64);
+                    require(
+                        TimingRule::CasLatency,
+                        None,
+                        Command::Read,
+                        self.read,
+                        cas_latency + read_beats,
+                    );
+                    let length = self
+                        .mode
+                        .map_or(1, |mode| mode.write_beats(&geometry) as u64);
+                    let end = clock + length - 1;
+                    self.write = Some(WriteBurst {
+                        bank,
+                        end,
+                        auto_precharge: pins.a10(),
+                    });
+                    if pins.a10() {
+                        auto_precharge = Some(end + clocks.wr);
+                    }
+                } else {
+                    self.read = Some(clock);
+                    if pins.a10() {
+                        auto_precharge = Some(clock + read_beats);
+                    }
+                }
+            }
+            Command::Precharge => {
+                let banks = if pins.a10() {
+                    0..self.geometry.banks
+                } else {
+                    bank..bank + 1
+                };
+                for bank in banks {
+                    let history = &mut self.banks[bank as usize];
+                    require(
+                        TimingRule::Ras,
+                        Some(bank),
+                        Command::Active,
+                        history.active,
+                        clocks.ras,
+                    );
+                    // measured from the last beat rather than the command
+                    require(
+                        TimingRule::Wr,
+                        Some(bank),
+                        Command::Write,
+                        history.write_data,
+                        clocks.wr,
+                    );
+                    history.precharge = Some(clock);
+                }
+            }
+            Command::Refresh | Command::LoadMode => {
+                for (index, history) in self.banks.iter().enumerate() {
+                    require(
+                        TimingRule::Rp,
+                        Some(index as u32),
+                        Command::Precharge,
+                        history.precharge,
+                        clocks.rp,
+                    );
+                }
+                require(
+                    TimingRule::Rfc,
+                    None,
+                    Command::Refresh,
+                    self.refresh,
+                    clocks.rfc,
+                );
+                if command == Command::Refresh {
+                    self.refresh = Some(clock);
+                } else {
+                    self.load_mode = Some(clock);
+                    if let Ok(mode) = ModeRegister::decode(pins.addr) {
+                        self.mode = Some(mode);
+                    }
+                }
+            }
+            Command::Deselect | Command::Nop | Command::BurstTerminate => {}
+        }
+
+        if let Some(at) = auto_precharge {
+            self.banks[bank as usize].precharge = Some(at);
+            if let Some(active) = history.active {
+                let elapsed = at.saturating_sub(active);
+                if elapsed < clocks.ras {
+                    violations.push(TimingViolation {
+                        rule: TimingRule::Ras,
+                        command,
+                        bank: Some(bank),
+                        since: Command::Active,
+                        elapsed,
+                        required: clocks.ras,
+                    });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GEOMETRY: Geometry = Geometry {
+        row_width: 4,
+        col_width: 8,
+        banks: 4,
+        data_width: 16,
+    };
+
+    /// In clocks at 1000 MHz, with tRC longer than tRAS + tRP so that
+    /// every rule can be broken alone.
+    const TIMING: Timing = Timing {
+        t_rc: 6,
+        t_ras: 3,
+        t_rcd: 2,
+        t_rp: 2,
+        t_rrd: 2,
+        t_rfc: 6,
+        t_wr: 2,
+        t_mrd: 2,
+        t_init: 0,
+        t_ref: 64_000_000,
+    };
+
+    /// BL2, CL2.
+    const MODE: u16 = 0x21;
+
+    const AUTO_PRECHARGE: u16 = 1 << 10;
+
+    fn at(clock: u64, command: Command, bank: u8, addr: u16) -> (u64, SdramPins) {
+        (clock, SdramPins::encode(command, bank, addr))
+    }
+
+    /// Rule and command of every violation of `schedule`, NOPs in between.
+    fn violations(timing: &Timing, schedule: &[(u64, SdramPins)]) -> Vec<(TimingRule, Command)> {
+        let mut checker = TimingChecker::new(GEOMETRY, timing, 1000);
+        let end = schedule.iter().map(|&(clock, _)| clock).max().unwrap();
+        (0..=end)
+            .flat_map(|clock| {
+                let pins = schedule
+                    .iter()
+                    .find(|&&(at, _)| at == clock)
+                    .map_or(SdramPins::encode(Command::Nop, 0, 0), |&(_, pins)| pins);
+                checker.tick(&pins)
+            })
+            .map(|violation| (violation.rule, violation.command))
+            .collect()
+    }
+
+    #[test]
+    fn checks_trcd() {
+        let active = at(0, Command::Active, 0, 0);
+        assert_eq!(
+            violations(&TIMING, &[active, at(2, Command::Read, 0, 0)]),
+            []
+        );
+        assert_eq!(
+            violations(&TIMING, &[active, at(1, Command::Write, 0, 0)]),
+            [(TimingRule::Rcd, Command::Write)]
+        );
+    }
+
+    #[test]
+    fn checks_tras() {
+        let active = at(0, Command::Active, 1, 0);
+        assert_eq!(
+            violations(&TIMING, &[active, at(3, Command::Precharge, 1, 0)]),
+            []
+        );
+        assert_eq!(
+            violations(&TIMING, &[active, at(2, Command::Precharge, 1, 0)]),
+            [(TimingRule::Ras, Command::Precharge)]
+        );
+        // PRECHARGE ALL holds every bank to it
+        assert_eq!(
+            violations(
+                &TIMING,
+                &[active, at(2, Command::Precharge, 0, AUTO_PRECHARGE)]
+            ),
+            [(TimingRule::Ras, Command::Precharge)]
+        );
+    }
+
+    #[test]
+    fn checks_trp() {
+        let active = at(0, Command::Active, 0, 0);
+        assert_eq!(
+            violations(
+                &TIMING,
+                &[
+                    active,
+                    at(4, Command::Precharge, 0, 0),
+                    at(6, Command::Active, 0, 0)
+                ]
+            ),
+            []
+        );
+        assert_eq!(
+            violations(
+                &TIMING,
+                &[
+                    active,
+                    at(5, Command::Precharge, 0, 0),
+                    at(6, Command::Active, 0, 0)
+                ]
+            ),
+            [(TimingRule::Rp, Command::Active)]
+        );
+        assert_eq!(
+            violations(
+                &TIMING,
+                &[
+                    active,
+                    at(5, Command::Precharge, 0, 0),
+                    at(6, Command::Refresh, 0, 0)
+                ]
+            ),
+            [(TimingRule::Rp, Command::Refresh)]
+        );
+    }
+
+    #[test]
+    fn checks_trc() {
+        let active = at(0, Command::Active, 2, 0);
+        let precharge = at(3, Command::Precharge, 2, 0);
+        assert_eq!(
+            violations(&TIMING, &[active, precharge, at(6, Command::Active, 2, 0)]),
+            []
+        );
+        assert_eq!(
+            violations(&TIMING, &[active, precharge, at(5, Command::Active, 2, 0)]),
+            [(TimingRule::Rc, Command::Active)]
+        );
+    }
+
+    #[test]
+    fn checks_trrd() {
+        let active = at(0, Command::Active, 0, 0);
+        assert_eq!(
+            violations(&TIMING, &[active, at(2, Command::Active, 1, 0)]),
+            []
+        );
+        assert_eq!(
+            violations(&TIMING, &[active, at(1, Command::Active, 1, 0)]),
+            [(TimingRule::Rrd, Command::Active)]
+        );
+    }
+
+    #[test]
+    fn checks_trfc() {
+        let refresh = at(0, Command::Refresh, 0, 0);
+        assert_eq!(
+            violations(&TIMING, &[refresh, at(6, Command::Active, 0, 0)]),
+            []
+        );
+        assert_eq!(
+            violations(&TIMING, &[refresh, at(5, Command::Refresh, 0, 0)]),
+            [(TimingRule::Rfc, Command::Refresh)]
+        );
+    }
+
+    #[test]
+    fn checks_tmrd() {
+        let load_mode = at(0, Command::LoadMode, 0, MODE);
+        assert_eq!(
+            violations(&TIMING, &[load_mode, at(2, Command::Active, 0, 0)]),
+            []
+        );
+        assert_eq!(
+            violations(&TIMING, &[load_mode, at(1, Command::Active, 0, 0)]),
+            [(TimingRule::Mrd, Command::Active)]
+        );
+    }
+
+    #[test]
+    fn checks_twr_from_the_last_beat() {
+        let setup = [
+            at(0, Command::LoadMode, 0, MODE),
+            at(2, Command::Active, 0, 0),
+            at(4, Command::Write, 0, 0),
+        ];
+        let precharge = |clock| [setup.as_slice(), &[at(clock, Command::Precharge, 0, 0)]].concat();
+        assert_eq!(violations(&TIMING, &precharge(7)), []);
+        assert_eq!(
+            violations(&TIMING, &precharge(6)),
+            [(TimingRule::Wr, Command::Precharge)]
+        );
+        // a beat DQM masks whole is not written
+        let masked = (
+            5,
+            SdramPins {
+                dqm: 0b11,
+                ..SdramPins::encode(Command::Nop, 0, 0)
+            },
+        );
+        let schedule = [setup.as_slice(), &[masked, at(6, Command::Precharge, 0, 0)]].concat();
+        assert_eq!(violations(&TIMING, &schedule), []);
+    }
+
+    #[test]
+    fn checks_the_read_burst_left_dq() {
+        let setup = [
+            at(0, Command::LoadMode, 0, MODE),
+            at(2, Command::Active, 0, 0),
+            at(4, Command::Read, 0, 0),
+        ];
+        let write = |clock| [setup.as_slice(), &[at(clock, Command::Write, 0, 0)]].concat();
+        assert_eq!(violations(&TIMING, &write(8)), []);
+        assert_eq!(
+            violations(&TIMING, &write(7)),
+            [(TimingRule::CasLatency, Command::Write)]
+        );
+    }
+
+    #[test]
+    fn auto_precharge_ends_tras_and_starts_trp() {
+        let setup = [
+            at(0, Command::LoadMode, 0, MODE),
+            at(2, Command::Active, 0, 0),
+        ];
+        // the write burst ends at 5, the precharge starts tWR later
+        let write = at(4, Command::Write, 0, AUTO_PRECHARGE);
+        let schedule = [setup.as_slice(), &[write, at(9, Command::Active, 0, 0)]].concat();
+        assert_eq!(violations(&TIMING, &schedule), []);
+        let schedule = [setup.as_slice(), &[write, at(8, Command::Active, 0, 0)]].concat();
+        assert_eq!(
+            violations(&TIMING, &schedule),
+            [(TimingRule::Rp, Command::Active)]
+        );
+        // cut after its first beat, it precharges tWR after that one
+        let schedule = [
+            setup.as_slice(),
+            &[
+                write,
+                at(5, Command::BurstTerminate, 0, 0),
+                at(8, Command::Active, 0, 0),
+            ],
+        ]
+        .concat();
+        assert_eq!(violations(&TIMING, &schedule), []);
+
+        // a READ precharges after its last beat, which is before tRAS
+        let timing = Timing { t_ras: 6, ..TIMING };
+        let read = at(4, Command::Read, 0, AUTO_PRECHARGE);
+        assert_eq!(
+            violations(&timing, &[setup.as_slice(), &[read]].concat()),
+            [(TimingRule::Ras, Command::Read)]
+        );
+        let read = at(6, Command::Read, 0, AUTO_PRECHARGE);
+        assert_eq!(
+            violations(&timing, &[setup.as_slice(), &[read]].concat()),
+            []
+        );
+    }
+}
@@ -0,0 +1,868 @@
+//! Reference model of the memory behind the controller, checked against every
+//! read-back.
+//!
+//! Memory is allocated in pages on first write, so the mapped regions may span
+//! the whole SDRAM. Unwritten memory reads as zero.
+
+use anyhow::{bail, ensure, Context, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+use tracing::info;
+
+use crate::dpi::{beat_addresses, beat_lanes, AxiReadPayload, AxiWritePayload};
+use crate::failure::FailureKind;
+use crate::parse_number;
+use crate::sdram::AddressMap;
+use common::rtl_config::RTLConfig;
+
+/// Where the region mapped when `+mem-regions=` is not given starts, aligned
+/// down to the capacity of the SDRAM so that it holds every chip select in
+/// order.
+const DEFAULT_BASE: u64 = 0xfc00_0000;
+
+const PAGE_SIZE: u64 = 4096;
+
+/// A mapped range of the AXI address space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct MemRegion {
+    pub(crate) base: u64,
+    pub(crate) size: u64,
+}
+
+impl MemRegion {
+    /// End of the region, exclusive.
+    pub(crate) fn end(&self) -> u64 {
+        self.base + self.size
+    }
+
+    pub(crate) fn contains(&self, addr: u64, bytes: u64) -> bool {
+        self.base <= addr && addr + bytes <= self.end()
+    }
+
+    /// Parse `<base>:<size>[,<base>:<size>...]`, numbers may be `0x` prefixed.
+    pub(crate) fn parse_list(input: &str) -> Result<Vec<Self>> {
+        input
+            .split(',')
+            .map(|region| {
+                let (base, size) = region
+                    .split_once(':')
+                    .with_context(|| format!("memory region `{region}` is not `<base>:<size>`"))?;
+                Ok(Self {
+                    base: parse_number(base)
+                        .with_context(|| format!("invalid memory region base `{base}`"))?,
+                    size: parse_number(size)
+                        .with_context(|| format!("invalid memory region size `{size}`"))?,
+                })
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for MemRegion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#x}..{:#x}", self.base, self.end())
+    }
+}
+
+/// A burst the shadow memory cannot take, it is not applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum AccessError {
+    /// Bytes outside every mapped region.
+    OutOfRange { addr: u64, bytes: u64 },
+    /// A WRAP burst not of 2, 4, 8 or 16 beats, or starting unaligned to
+    /// its transfer size.
+    IllegalWrap { addr: u32, len: u8, size: u8 },
+    /// Write strobes on lanes the beat does not occupy.
+    IllegalStrobe { beat: usize, strb: u8, lanes: u8 },
+}
+
+impl AccessError {
+    /// What the failure counts as, a burst AXI4 forbids breaks the protocol.
+    pub(crate) fn kind(&self) -> FailureKind {
+        match self {
+            Self::OutOfRange { .. } => FailureKind::Range,
+            Self::IllegalWrap { .. } | Self::IllegalStrobe { .. } => FailureKind::Axi,
+        }
+    }
+}
+
+impl fmt::Display for AccessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OutOfRange { addr, bytes } => write!(
+                f,
+                "access of {bytes} bytes at 0x{addr:08x} is outside the mapped memory"
+            ),
+            Self::IllegalWrap { addr, len, size } => write!(
+                f,
+                "WRAP burst of {} beats of {} bytes at 0x{addr:08x} is illegal",
+                *len as u32 + 1,
+                1u32 << size
+            ),
+            Self::IllegalStrobe { beat, strb, lanes } => write!(
+                f,
+                "strobe 0b{strb:08b} of beat {beat} is outside the lanes 0b{lanes:08b} of the transfer"
+            ),
+        }
+    }
+}
+
+/// Read data a beat must carry on its active byte lanes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ExpectedBeat {
+    pub(crate) data: u32,
+    pub(crate) lanes: u8,
+}
+
+impl ExpectedBeat {
+    /// Bits of the data bus covered by `lanes`.
+    pub(crate) fn mask(&self) -> u32 {
+        (0..4)
+            .filter(|lane| (self.lanes >> lane) & 1 != 0)
+            .fold(0, |mask, lane| mask | 0xff << (lane * 8))
+    }
+
+    pub(crate) fn matches(&self, rdata: u32) -> bool {
+        rdata & self.mask() == self.data
+    }
+}
+
+/// How finely the shadow memory records which write each byte comes from,
+/// selected with `+provenance=<byte|word|off>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Provenance {
+    /// One record per byte, 8 bytes of memory per byte of a page written.
+    Byte,
+    /// One record per bus word, the last write to any of its bytes.
+    Word,
+    Off,
+}
+
+impl FromStr for Provenance {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        match input {
+            "byte" => Ok(Self::Byte),
+            "word" => Ok(Self::Word),
+            "off" => Ok(Self::Off),
+            _ => bail!("provenance `{input}` is not one of `byte`, `word` or `off`"),
+        }
+    }
+}
+
+/// A write the shadow memory took data from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct WriteRecord {
+    pub(crate) id: u8,
+    /// Order the write was issued in, from 1.
+    pub(crate) seq: u64,
+    /// Tick the write was handed to the agent at.
+    pub(crate) issue_tick: u64,
+}
+
+impl WriteRecord {
+    pub(crate) fn of(payload: &AxiWritePayload) -> Self {
+        Self {
+            id: payload.id,
+            seq: payload.issue_seq,
+            issue_tick: payload.issue_tick,
+        }
+    }
+}
+
+impl fmt::Display for WriteRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "write #{} (id 0x{:02x}) issued at tick {}",
+            self.seq, self.id, self.issue_tick
+        )
+    }
+}
+
+/// The write, and beat of it, the content of a byte comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Origin {
+    #[serde(flatten)]
+    pub(crate) write: WriteRecord,
+    pub(crate) beat: u8,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "beat {} of {}", self.beat, self.write)
+    }
+}
+
+/// Index in `ShadowMem::writes` and beat of the last write of a byte or word.
+#[derive(Debug, Clone, Copy, Default)]
+struct Written {
+    write: u32,
+    beat: u8,
+}
+
+struct Page {
+    data: [u8; PAGE_SIZE as usize],
+    /// One bit per byte ever written.
+    written: [u64; PAGE_SIZE as usize / 64],
+    /// Last write of each byte or word, empty without provenance.
+    origins: Vec<Written>,
+}
+
+impl Page {
+    fn is_written(&self, offset: usize) -> bool {
+        (self.written[offset / 64] >> (offset % 64)) & 1 != 0
+    }
+}
+
+pub(crate) struct ShadowMem {
+    regions: Vec<MemRegion>,
+    bus_bytes: u8,
+    /// Only used to annotate the log.
+    address_map: AddressMap,
+    pages: HashMap<u64, Box<Page>>,
+    provenance: Provenance,
+    /// Every write taken, when provenance is kept.
+    writes: Vec<WriteRecord>,
+}
+
+impl ShadowMem {
+    /// Map `regions`, or as much of the SDRAM as there is from `DEFAULT_BASE`
+    /// if there are none. Regions may not alias, as the controller ignores
+    /// address bits above the chip select.
+    pub fn new(
+        config: &RTLConfig,
+        mut regions: Vec<MemRegion>,
+        address_map: AddressMap,
+        provenance: Provenance,
+    ) -> Result<Self> {
+        let axi = config.axi();
+        let space = 1u64 << axi.addr_width;
+        let capacity = address_map.capacity();
+        if regions.is_empty() {
+            let base = (DEFAULT_BASE / capacity * capacity).min(space.saturating_sub(capacity));
+            regions.push(MemRegion {
+                base,
+                size: capacity.min(space - base),
+            });
+        }
+
+        regions.sort_by_key(|region| region.base);
+        for region in &regions {
+            ensure!(
+                region.size > 0 && region.base % PAGE_SIZE == 0 && region.size % PAGE_SIZE == 0,
+                "memory region {region} must be {PAGE_SIZE} bytes aligned"
+            );
+            ensure!(
+                region.end() <= space,
+                "memory region {region} is outside the {}-bit address space",
+                axi.addr_width
+            );
+        }
+        for pair in regions.windows(2) {
+            ensure!(
+                pair[0].end() <= pair[1].base,
+                "memory regions {} and {} overlap",
+                pair[0],
+                pair[1]
+            );
+        }
+        // where each region lands in the first repetition of the map, one
+        // wrapping around its end is two ranges
+        let mut offsets: Vec<(Range<u64>, &MemRegion)> = Vec::new();
+        for region in &regions {
+            ensure!(
+                region.size <= capacity,
+                "memory region {region} is larger than the {capacity:#x} bytes of SDRAM, it aliases itself"
+            );
+            let start = region.base % capacity;
+            offsets.push((start..(start + region.size).min(capacity), region));
+            if start + region.size > capacity {
+                offsets.push((0..start + region.size - capacity, region));
+            }
+        }
+        offsets.sort_by_key(|(range, _)| range.start);
+        for pair in offsets.windows(2) {
+            ensure!(
+                pair[0].0.end <= pair[1].0.start,
+                "memory regions {} and {} alias, the SDRAM repeats every {capacity:#x} bytes",
+                pair[0].1,
+                pair[1].1
+            );
+        }
+
+        Ok(Self {
+            regions,
+            bus_bytes: axi.bus_bytes() as u8,
+            address_map,
+            pages: HashMap::new(),
+            provenance,
+            writes: Vec::new(),
+        })
+    }
+
+    pub fn regions(&self) -> &[MemRegion] {
+        &self.regions
+    }
+
+    fn check_range(&self, addr: u32, bytes: u64) -> Result<(), AccessError> {
+        let addr = addr as u64;
+        if self
+            .regions
+            .iter()
+            .any(|region| region.contains(addr, bytes))
+        {
+            Ok(())
+        } else {
+            Err(AccessError::OutOfRange { addr, bytes })
+        }
+    }
+
+    fn read_byte(&self, addr: u64) -> u8 {
+        self.pages
+            .get(&(addr / PAGE_SIZE))
+            .map_or(0, |page| page.data[(addr % PAGE_SIZE) as usize])
+    }
+
+    /// Bytes sharing a provenance record, as a shift of the offset.
+    fn origin_shift(&self) -> u32 {
+        match self.provenance {
+            Provenance::Word => self.bus_bytes.trailing_zeros(),
+            Provenance::Byte | Provenance::Off => 0,
+        }
+    }
+
+    /// Write a byte from `beat` of the last of `writes`.
+    fn write_byte(&mut self, addr: u64, value: u8, beat: u8) {
+        let shift = self.origin_shift();
+        let origins = match self.provenance {
+            Provenance::Off => 0,
+            Provenance::Byte | Provenance::Word => PAGE_SIZE as usize >> shift,
+        };
+        let page = self.pages.entry(addr / PAGE_SIZE).or_insert_with(|| {
+            Box::new(Page {
+                data: [0; PAGE_SIZE as usize],
+                written: [0; PAGE_SIZE as usize / 64],
+                origins: vec![Written::default(); origins],
+            })
+        });
+        let offset = (addr % PAGE_SIZE) as usize;
+        page.data[offset] = value;
+        page.written[offset / 64] |= 1 << (offset % 64);
+        if let Some(origin) = page.origins.get_mut(offset >> shift) {
+            *origin = Written {
+                write: self.writes.len() as u32 - 1,
+                beat,
+            };
+        }
+    }
+
+    /// The write the current content of a byte comes from, `None` if it was
+    /// never written or provenance is off. With word provenance it is the
+    /// last write of any byte of the word.
+    pub fn last_writer(&self, addr: u64) -> Option<Origin> {
+        let page = self.pages.get(&(addr / PAGE_SIZE))?;
+        let offset = (addr % PAGE_SIZE) as usize;
+        if !page.is_written(offset) {
+            return None;
+        }
+        let origin = page.origins.get(offset >> self.origin_shift())?;
+        Some(Origin {
+            write: self.writes[origin.write as usize],
+            beat: origin.beat,
+        })
+    }
+
+    /// Every byte written so far and its value, in address order.
+    pub fn written_bytes(&self) -> Vec<(u64, u8)> {
+        let mut pages: Vec<_> = self.pages.iter().collect();
+        pages.sort_by_key(|(index, _)| **index);
+        pages
+            .into_iter()
+            .flat_map(|(index, page)| {
+                (0..PAGE_SIZE as usize)
+                    .filter(|&offset| page.is_written(offset))
+                    .map(move |offset| (index * PAGE_SIZE + offset as u64, page.data[offset]))
+            })
+            .collect()
+    }
+
+    fn is_addr_align(&self, addr: u32, size: u8) -> bool {
+        let bytes_number = 1 << size;
+        let aligned_addr = addr / bytes_number * bytes_number;
+        addr == aligned_addr
+    }
+
+    fn check_burst(&self, addr: u32, burst: u8, len: u8, size: u8) -> Result<(), AccessError> {
+        if burst == 2 && !(matches!(len, 1 | 3 | 7 | 15) && self.is_addr_align(addr, size)) {
+            return Err(AccessError::IllegalWrap { addr, len, size });
+        }
+        Ok(())
+    }
+
+    /// Check every active lane of a burst is mapped, returning the lanes of
+    /// each beat.
+    fn burst_lanes(
+        &self,
+        addr: u32,
+        burst: u8,
+        len: u8,
+        size: u8,
+    ) -> Result<Vec<(u32, u8)>, AccessError> {
+        self.check_burst(addr, burst, len, size)?;
+        beat_addresses(addr, burst, len, size)
+            .into_iter()
+            .map(|beat_addr| {
+                let lanes = beat_lanes(beat_addr, size, self.bus_bytes as u32);
+                let bus_addr = beat_addr / self.bus_bytes as u32 * self.bus_bytes as u32;
+                let first = bus_addr + lanes.trailing_zeros();
+                let bytes = lanes.count_ones() as u64;
+                self.check_range(first, bytes)?;
+                Ok((bus_addr, lanes))
+            })
+            .collect()
+    }
+
+    /// Expected data of every beat of a read, on the lanes given by the
+    /// address as in AXI4 spec A3.4.3.
+    pub fn read_mem_axi(&self, payload: AxiReadPayload) -> Result<Vec<ExpectedBeat>, AccessError> {
+        let beats = self.burst_lanes(payload.addr, payload.burst, payload.len, payload.size)?;
+        Ok(beats
+            .into_iter()
+            .map(|(bus_addr, lanes)| ExpectedBeat {
+                data: (0..self.bus_bytes)
+                    .filter(|lane| (lanes >> lane) & 1 != 0)
+                    .fold(0, |data, lane| {
+                        data | (self.read_byte(bus_addr as u64 + lane as u64) as u32) << (lane * 8)
+                    }),
+                lanes,
+            })
+            .collect())
+    }
+
+    /// Write the strobed lanes of every beat, a burst is either written as a
+    /// whole or not at all.
+    pub fn write_mem_axi(&mut self, payload: AxiWritePayload) -> Result<(), AccessError> {
+        let beats = self.burst_lanes(payload.addr, payload.burst, payload.len, payload.size)?;
+        for (beat, &(_, lanes)) in beats.iter().enumerate() {
+            let strb = payload.strb[beat];
+            if strb & !lanes != 0 {
+                return Err(AccessError::IllegalStrobe { beat, strb, lanes });
+            }
+        }
+        if self.provenance != Provenance::Off {
+            self.writes.push(WriteRecord::of(&payload));
+        }
+        for (item_idx, (bus_addr, _)) in beats.into_iter().enumerate() {
+            let strb = payload.strb[item_idx];
+            if strb == 0 {
+                continue;
+            }
+
+            info!(
+                "writing(0x{:02x}) 0x{:08x} -> 0x{:08x} ({})/{} with strb:0b{:04b}",
+                payload.id,
+                payload.data[item_idx],
+                bus_addr,
+                self.address_map.decode(bus_addr as u64),
+                match payload.burst {
+                    0 => "FIX",
+                    1 => "INCR",
+                    2 => "WARP",
+                    _ => "UNKNOWN",
+                },
+                strb
+            );
+
+            for lane in 0..self.bus_bytes {
+                if (strb >> lane) & 1 != 0 {
+                    self.write_byte(
+                        bus_addr as u64 + lane as u64,
+                        (payload.data[item_idx] >> (lane * 8) & 0xff) as u8,
+                        item_idx as u8,
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sdram::Geometry;
+
+    const RTL_CONFIG: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../configs/SDRAMControllerTestBenchMain.json"
+    );
+
+    /// One 16 KiB chip select behind the 32-bit AXI bus of the test bench.
+    const CAPACITY: u64 = 0x4000;
+
+    fn mem(regions: &str, provenance: Provenance) -> Result<ShadowMem> {
+        let config = RTLConfig::from_file(RTL_CONFIG).unwrap();
+        let geometry = Geometry {
+            row_width: 3,
+            col_width: 8,
+            banks: 4,
+            data_width: 16,
+        };
+        let regions = if regions.is_empty() {
+            Vec::new()
+        } else {
+            MemRegion::parse_list(regions)?
+        };
+        ShadowMem::new(&config, regions, AddressMap::new(geometry, 1), provenance)
+    }
+
+    fn write(addr: u32, burst: u8, size: u8, data: &[u32], strb: &[u8]) -> AxiWritePayload {
+        AxiWritePayload {
+            id: 1,
+            len: data.len() as u8 - 1,
+            addr,
+            data: data.to_vec(),
+            strb: strb.to_vec(),
+            wUser: vec![0; data.len()],
+            awUser: 0,
+            dataValid: 1,
+            burst,
+            cache: 0,
+            lock: 0,
+            prot: 0,
+            qos: 0,
+            region: 0,
+            size,
+            issue_seq: 0,
+            issue_tick: 0,
+        }
+    }
+
+    fn read(addr: u32, burst: u8, len: u8, size: u8) -> AxiReadPayload {
+        AxiReadPayload {
+            addr,
+            id: 1,
+            user: 0,
+            burst,
+            cache: 0,
+            len,
+            lock: 0,
+            prot: 0,
+            qos: 0,
+            region: 0,
+            size,
+            valid: 1,
+        }
+    }
+
+    #[test]
+    fn parses_region_lists() {
+        assert_eq!(
+            MemRegion::parse_list("0x1000:0x2000,16384:4096").unwrap(),
+            [
+                MemRegion {
+                    base: 0x1000,
+                    size: 0x2000
+                },
+                MemRegion {
+                    base: 0x4000,
+                    size: 0x1000
+                },
+            ]
+        );
+        assert!(MemRegion::parse_list("0x1000").is_err());
+        assert!(MemRegion::parse_list("0x1000:").is_err());
+        assert!(MemRegion::parse_list("base:0x1000").is_err());
+    }
+
+    #[test]
+    fn maps_the_whole_sdram_by_default() {
+        let mem = mem("", Provenance::Off).unwrap();
+        assert_eq!(
+            mem.regions(),
+            [MemRegion {
+                base: DEFAULT_BASE,
+                size: CAPACITY
+            }]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_regions() {
+        // not page aligned
+        assert!(mem("0x800:0x1000", Provenance::Off).is_err());
+        assert!(mem("0x1000:0x800", Provenance::Off).is_err());
+        assert!(mem("0x1000:0", Provenance::Off).is_err());
+        // past the 32-bit address space
+        assert!(mem("0xfffff000:0x2000", Provenance::Off).is_err());
+        // overlapping
+        assert!(mem("0x0:0x2000,0x1000:0x1000", Provenance::Off).is_err());
+        // larger than the SDRAM
+        assert!(mem("0x0:0x8000", Provenance::Off).is_err());
+    }
+
+    #[test]
+    fn rejects_aliasing_regions() {
+        assert!(mem("0x0:0x1000,0x4000:0x1000", Provenance::Off).is_err());
+        // the second half of a region wrapping around the end of the SDRAM
+        assert!(mem("0x3000:0x2000,0x8000:0x1000", Provenance::Off).is_err());
+        mem("0x3000:0x2000,0x9000:0x1000", Provenance::Off).unwrap();
+        mem("0x0:0x2000,0x6000:0x2000", Provenance::Off).unwrap();
+    }
+
+    #[test]
+    fn backs_only_written_pages() {
+        let mut mem = mem("0x0:0x4000", Provenance::Off).unwrap();
+        mem.write_mem_axi(write(0x10, 1, 2, &[0x4433_2211], &[0xf]))
+            .unwrap();
+        mem.write_mem_axi(write(0x3002, 1, 1, &[0xbbaa_0000], &[0xc]))
+            .unwrap();
+        assert_eq!(mem.pages.len(), 2);
+        assert_eq!(
+            mem.written_bytes(),
+            [
+                (0x10, 0x11),
+                (0x11, 0x22),
+                (0x12, 0x33),
+                (0x13, 0x44),
+                (0x3002, 0xaa),
+                (0x3003, 0xbb)
+            ]
+        );
+        let beats = mem.read_mem_axi(read(0x10, 1, 1, 2)).unwrap();
+        assert_eq!(beats[0].data, 0x4433_2211);
+        // never written, in a backed and an unbacked page
+        assert_eq!(beats[1].data, 0);
+        assert_eq!(mem.read_mem_axi(read(0x2000, 1, 0, 2)).unwrap()[0].data, 0);
+        assert_eq!(mem.pages.len(), 2);
+    }
+
+    #[test]
+    fn detects_accesses_out_of_range() {
+        let mut mem = mem("0x1000:0x1000", Provenance::Off).unwrap();
+        let err = mem.read_mem_axi(read(0xffc, 1, 0, 2)).unwrap_err();
+        assert_eq!(
+            err,
+            AccessError::OutOfRange {
+                addr: 0xffc,
+                bytes: 4
+            }
+        );
+        assert_eq!(err.kind(), FailureKind::Range);
+        // only the last beat is out of range, none of the burst is written
+        let err = mem
+            .write_mem_axi(write(0x1ff8, 1, 2, &[1, 2, 3], &[0xf; 3]))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            AccessError::OutOfRange {
+                addr: 0x2000,
+                bytes: 4
+            }
+        );
+        assert!(mem.written_bytes().is_empty());
+        // the active lanes of a narrow beat are in range
+        mem.read_mem_axi(read(0x1ffe, 0, 0, 1)).unwrap();
+    }
+
+    #[test]
+    fn reports_illegal_wraps_and_strobes() {
+        let mut mem = mem("0x0:0x1000", Provenance::Off).unwrap();
+        for (addr, len) in [(0x0, 2), (0x2, 3)] {
+            let err = mem.read_mem_axi(read(addr, 2, len, 2)).unwrap_err();
+            assert_eq!(err, AccessError::IllegalWrap { addr, len, size: 2 });
+            assert_eq!(err.kind(), FailureKind::Axi);
+        }
+        let err = mem
+            .write_mem_axi(write(0x0, 1, 1, &[1, 2], &[0x3, 0x1]))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            AccessError::IllegalStrobe {
+                beat: 1,
+                strb: 0x1,
+                lanes: 0xc
+            }
+        );
+        assert_eq!(err.kind(), FailureKind::Axi);
+        assert!(mem.written_bytes().is_empty());
+    }
+
+    #[test]
+    fn selects_the_lanes_of_narrow_bursts() {
+        let mem = mem("0x0:0x1000", Provenance::Off).unwrap();
+        // FIXED repeats the lanes of its address
+        assert_eq!(mem.burst_lanes(0x101, 0, 2, 0).unwrap(), [(0x100, 0x2); 3]);
+        assert_eq!(mem.burst_lanes(0x102, 0, 1, 1).unwrap(), [(0x100, 0xc); 2]);
+        // INCR only has an unaligned first beat
+        assert_eq!(
+            mem.burst_lanes(0x101, 1, 3, 1).unwrap(),
+            [(0x100, 0x2), (0x100, 0xc), (0x104, 0x3), (0x104, 0xc)]
+        );
+        assert_eq!(
+            mem.burst_lanes(0x3, 1, 2, 0).unwrap(),
+            [(0x0, 0x8), (0x4, 0x1), (0x4, 0x2)]
+        );
+        // WRAP goes back to the start of its 8 byte window
+        assert_eq!(
+            mem.burst_lanes(0x106, 2, 3, 1).unwrap(),
+            [(0x104, 0xc), (0x100, 0x3), (0x100, 0xc), (0x104, 0x3)]
+        );
+        assert_eq!(
+            mem.burst_lanes(0x104, 2, 1, 2).unwrap(),
+            [(0x104, 0xf), (0x100, 0xf)]
+        );
+    }
+
+    #[test]
+    fn writes_and_expects_only_strobed_lanes() {
+        let mut mem = mem("0x0:0x1000", Provenance::Off).unwrap();
+        mem.write_mem_axi(write(
+            0x100,
+            1,
+            1,
+            &[0x0000_11aa, 0xbb22_0000, 0x0000_ddcc, 0x4433_0000],
+            &[0x1, 0x8, 0x3, 0x0],
+        ))
+        .unwrap();
+        assert_eq!(
+            mem.written_bytes(),
+            [(0x100, 0xaa), (0x103, 0xbb), (0x104, 0xcc), (0x105, 0xdd)]
+        );
+        assert_eq!(
+            mem.read_mem_axi(read(0x103, 0, 1, 0)).unwrap(),
+            [ExpectedBeat {
+                data: 0xbb00_0000,
+                lanes: 0x8
+            }; 2]
+        );
+        let beats = mem.read_mem_axi(read(0x102, 2, 3, 1)).unwrap();
+        assert_eq!(
+            beats,
+            [
+                ExpectedBeat {
+                    data: 0xbb00_0000,
+                    lanes: 0xc
+                },
+                ExpectedBeat {
+                    data: 0x0000_ddcc,
+                    lanes: 0x3
+                },
+                ExpectedBeat {
+                    data: 0,
+                    lanes: 0xc
+                },
+                ExpectedBeat {
+                    data: 0x0000_00aa,
+                    lanes: 0x3
+                },
+            ]
+        );
+        // inactive lanes are not compared
+        assert_eq!(beats[0].mask(), 0xffff_0000);
+        assert!(beats[0].matches(0xbb00_1234));
+        assert!(!beats[0].matches(0xba00_0000));
+        assert!(beats[1].matches(0x5555_ddcc));
+    }
+
+    /// A full word at 0x100, then its byte 2 and byte 1 of the next one
+    /// overwritten by a narrow INCR burst.
+    fn overwrite(mem: &mut ShadowMem) {
+        let mut first = write(0x100, 1, 2, &[0x4433_2211, 0x8877_6655], &[0xf, 0xf]);
+        first.issue_seq = 1;
+        first.issue_tick = 10;
+        mem.write_mem_axi(first).unwrap();
+        let mut second = write(0x102, 1, 1, &[0x00cc_0000, 0x0000_00dd], &[0xc, 0x1]);
+        second.id = 2;
+        second.issue_seq = 2;
+        second.issue_tick = 20;
+        mem.write_mem_axi(second).unwrap();
+    }
+
+    /// Order and beat of the write each byte from 0x100 on was last written
+    /// by.
+    fn writers(mem: &ShadowMem, bytes: u64) -> Vec<Option<(u64, u8)>> {
+        (0x100..0x100 + bytes)
+            .map(|addr| {
+                mem.last_writer(addr)
+                    .map(|origin| (origin.write.seq, origin.beat))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn keeps_the_last_writer_of_each_byte() {
+        let mut mem = mem("0x0:0x1000", Provenance::Byte).unwrap();
+        overwrite(&mut mem);
+        assert_eq!(
+            writers(&mem, 9),
+            [
+                Some((1, 0)),
+                Some((1, 0)),
+                Some((2, 0)),
+                Some((2, 0)),
+                Some((2, 1)),
+                Some((1, 1)),
+                Some((1, 1)),
+                Some((1, 1)),
+                None,
+            ]
+        );
+        assert_eq!(
+            mem.last_writer(0x104),
+            Some(Origin {
+                write: WriteRecord {
+                    id: 2,
+                    seq: 2,
+                    issue_tick: 20,
+                },
+                beat: 1,
+            })
+        );
+        let data: Vec<u8> = mem
+            .written_bytes()
+            .into_iter()
+            .map(|(_, byte)| byte)
+            .collect();
+        assert_eq!(data, [0x11, 0x22, 0xcc, 0x00, 0xdd, 0x66, 0x77, 0x88]);
+    }
+
+    #[test]
+    fn keeps_the_last_writer_of_each_word() {
+        let mut mem = mem("0x0:0x1000", Provenance::Word).unwrap();
+        overwrite(&mut mem);
+        // any byte written makes the whole word its
+        assert_eq!(
+            writers(&mem, 9),
+            [
+                Some((2, 0)),
+                Some((2, 0)),
+                Some((2, 0)),
+                Some((2, 0)),
+                Some((2, 1)),
+                Some((2, 1)),
+                Some((2, 1)),
+                Some((2, 1)),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_no_writer_without_provenance() {
+        let mut mem = mem("0x0:0x1000", Provenance::Off).unwrap();
+        overwrite(&mut mem);
+        assert_eq!(writers(&mem, 9), [None; 9]);
+        assert!(mem.writes.is_empty());
+        assert_eq!(mem.written_bytes().len(), 8);
+        assert_eq!("word".parse::<Provenance>().unwrap(), Provenance::Word);
+        assert!("bytes".parse::<Provenance>().is_err());
+    }
+}
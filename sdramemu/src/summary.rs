@@ -0,0 +1,44 @@
+//! Machine-readable summary of a run, written when it ends to the file given
+//! with `+run-summary=<path>`, `run-summary.json` by default.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::backend::WatchdogStatus;
+use crate::fnv1a;
+
+/// Where the summary goes when `+run-summary=` is not given.
+pub(crate) const DEFAULT_SUMMARY_PATH: &str = "run-summary.json";
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RunSummary {
+    pub(crate) result: WatchdogStatus,
+    /// Seed of the stimulus, as `+seed=` takes it.
+    pub(crate) seed: String,
+    /// Tick the run ended at.
+    pub(crate) tick: u64,
+    pub(crate) first_failure_tick: Option<u64>,
+    pub(crate) config_hash: String,
+    /// Transactions issued and completed.
+    pub(crate) counters: BTreeMap<String, u64>,
+    /// Failures of each kind, see `failure.rs`.
+    pub(crate) failures: BTreeMap<String, u64>,
+}
+
+impl RunSummary {
+    pub(crate) fn write(&self, path: &Path) -> Result<()> {
+        let mut json = serde_json::to_string_pretty(self)?;
+        json.push('\n');
+        std::fs::write(path, json)
+            .with_context(|| format!("failed to write run summary `{}`", path.display()))
+    }
+}
+
+/// Hash of the content of the rtl config file, to tell which runs share a
+/// configuration.
+pub(crate) fn config_hash(config: &[u8]) -> String {
+    format!("{:016x}", fnv1a(0, config.iter().copied()))
+}
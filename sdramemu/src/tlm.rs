@@ -0,0 +1,201 @@
+//! Transaction-level model of the controller's AXI4 slave side.
+//!
+//! `TlmController` stands in for the RTL and the AXI4MasterAgent: every cycle
+//! it offers both request channels to an `AxiMaster`, and answers accepted
+//! requests with B and R responses after a random latency. Responses of
+//! different IDs may overtake each other, responses of one ID never do.
+//!
+//! Responses are delivered the way the agent does, in particular `len` of a
+//! read counts beats in 8 bits and the last beat is passed separately.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+use crate::dpi::{beat_addresses, beat_lanes, AxiReadPayload, AxiWritePayload};
+use crate::PAYLOAD_SIZE;
+
+/// The DPI callbacks of the AXI4MasterAgent.
+pub trait AxiMaster {
+    fn axi_write_ready(&mut self) -> AxiWritePayload;
+    fn axi_write_done(&mut self, bid: u8, bresp: u8, buser: u8);
+    fn axi_read_ready(&mut self) -> AxiReadPayload;
+    fn axi_read_done(
+        &mut self,
+        rdata: Vec<u32>,
+        len: u8,
+        last_data: u32,
+        rid: u8,
+        rresp: u8,
+        ruser: u8,
+    );
+}
+
+#[derive(Debug, Clone)]
+pub struct TlmConfig {
+    /// Cycles from accepting a request to its response.
+    pub latency: RangeInclusive<u64>,
+    /// Let responses of different IDs complete out of order.
+    pub reorder: bool,
+    pub seed: u64,
+    /// AXI data bus width in bytes.
+    pub bus_bytes: u32,
+}
+
+impl Default for TlmConfig {
+    fn default() -> Self {
+        Self {
+            latency: 1..=16,
+            reorder: false,
+            seed: 0,
+            bus_bytes: 4,
+        }
+    }
+}
+
+struct Pending<T> {
+    due: u64,
+    id: u8,
+    payload: T,
+}
+
+/// Index of the response to deliver at `cycle`, if any is due.
+fn next_response<T>(
+    entries: &[Pending<T>],
+    cycle: u64,
+    reorder: bool,
+    rng: &mut StdRng,
+) -> Option<usize> {
+    if !reorder {
+        return (entries.first()?.due <= cycle).then_some(0);
+    }
+    // only the oldest request of each ID may complete
+    let candidates: Vec<usize> = (0..entries.len())
+        .filter(|&index| {
+            entries[index].due <= cycle
+                && !entries[..index]
+                    .iter()
+                    .any(|older| older.id == entries[index].id)
+        })
+        .collect();
+    (!candidates.is_empty()).then(|| candidates[rng.gen_range(0..candidates.len())])
+}
+
+pub struct TlmController {
+    config: TlmConfig,
+    rng: StdRng,
+    cycle: u64,
+    mem: HashMap<u64, u8>,
+    // both in acceptance order
+    writes: Vec<Pending<AxiWritePayload>>,
+    reads: Vec<Pending<AxiReadPayload>>,
+}
+
+impl TlmController {
+    pub fn new(config: TlmConfig) -> Self {
+        assert!(
+            !config.latency.is_empty(),
+            "latency range must not be empty"
+        );
+        Self {
+            rng: StdRng::seed_from_u64(config.seed),
+            config,
+            cycle: 0,
+            mem: HashMap::new(),
+            writes: Vec::new(),
+            reads: Vec::new(),
+        }
+    }
+
+    pub fn cycle(&self) -> u64 {
+        self.cycle
+    }
+
+    /// Requests accepted but not yet responded to.
+    pub fn in_flight(&self) -> usize {
+        self.writes.len() + self.reads.len()
+    }
+
+    /// Run `cycles` clock cycles.
+    pub fn run(&mut self, master: &mut impl AxiMaster, cycles: u64) {
+        for _ in 0..cycles {
+            self.step(master);
+        }
+    }
+
+    /// Run one clock cycle: accept at most one write and one read, then
+    /// deliver at most one B and one R response.
+    pub fn step(&mut self, master: &mut impl AxiMaster) {
+        let write = master.axi_write_ready();
+        if write.dataValid != 0 {
+            let due = self.cycle + self.rng.gen_range(self.config.latency.clone());
+            self.writes.push(Pending {
+                due,
+                id: write.id,
+                payload: write,
+            });
+        }
+        let read = master.axi_read_ready();
+        if read.valid != 0 {
+            let due = self.cycle + self.rng.gen_range(self.config.latency.clone());
+            self.reads.push(Pending {
+                due,
+                id: read.id,
+                payload: read,
+            });
+        }
+
+        let reorder = self.config.reorder;
+        if let Some(index) = next_response(&self.writes, self.cycle, reorder, &mut self.rng) {
+            let write = self.writes.remove(index).payload;
+            self.write(&write);
+            master.axi_write_done(write.id, 0, 0);
+        }
+        if let Some(index) = next_response(&self.reads, self.cycle, reorder, &mut self.rng) {
+            let read = self.reads.remove(index).payload;
+            let mut rdata = self.read(&read);
+            let last_data = rdata.pop().unwrap();
+            let len = (rdata.len() + 1) as u8;
+            rdata.resize(PAYLOAD_SIZE, 0);
+            master.axi_read_done(rdata, len, last_data, read.id, 0, 0);
+        }
+
+        self.cycle += 1;
+    }
+
+    fn write(&mut self, payload: &AxiWritePayload) {
+        let beats = beat_addresses(payload.addr, payload.burst, payload.len, payload.size);
+        for (beat, beat_addr) in beats.into_iter().enumerate() {
+            let strb =
+                payload.strb[beat] & beat_lanes(beat_addr, payload.size, self.config.bus_bytes);
+            let bus_addr = (beat_addr / self.config.bus_bytes * self.config.bus_bytes) as u64;
+            for lane in 0..self.config.bus_bytes {
+                if (strb >> lane) & 1 != 0 {
+                    self.mem.insert(
+                        bus_addr + lane as u64,
+                        (payload.data[beat] >> (lane * 8)) as u8,
+                    );
+                }
+            }
+        }
+    }
+
+    /// Every beat of a read, as the whole bus word holding it.
+    fn read(&self, payload: &AxiReadPayload) -> Vec<u32> {
+        beat_addresses(payload.addr, payload.burst, payload.len, payload.size)
+            .into_iter()
+            .map(|beat_addr| {
+                let bus_addr = (beat_addr / self.config.bus_bytes * self.config.bus_bytes) as u64;
+                (0..self.config.bus_bytes).fold(0, |data, lane| {
+                    let byte = self
+                        .mem
+                        .get(&(bus_addr + lane as u64))
+                        .copied()
+                        .unwrap_or(0);
+                    data | (byte as u32) << (lane * 8)
+                })
+            })
+            .collect()
+    }
+}